@@ -16,6 +16,8 @@ mod day13;
 mod day14;
 mod day15;
 mod day16;
+mod day17;
+mod day18;
 
 pub static DAYS: &[Day] = &[
     day01::DAY,
@@ -34,4 +36,6 @@ pub static DAYS: &[Day] = &[
     day14::DAY,
     day15::DAY,
     day16::DAY,
+    day17::DAY,
+    day18::DAY,
 ];
@@ -6,7 +6,11 @@ pub const DAY: Day = Day {
     name: "The Treachery of Whales",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse)],
+    other: &[
+        ("Parse", run_parse),
+        ("Part 1 Meeting Point", run_part1_meeting_point),
+        ("Part 2 Meeting Point", run_part2_meeting_point),
+    ],
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
@@ -20,6 +24,24 @@ fn run_part2(input: &str, b: Bench) -> BenchResult {
     b.bench(|| find_fuel(&crabs, part2_fuel))
 }
 
+fn run_part1_meeting_point(input: &str, b: Bench) -> BenchResult {
+    let crabs = group_crabs(&parse(input).map_err(UserError)?);
+    b.bench(|| {
+        find_meeting_point(&crabs, Metric::L1)
+            .map(|mp| mp.cost)
+            .ok_or("No crabs!?")
+    })
+}
+
+fn run_part2_meeting_point(input: &str, b: Bench) -> BenchResult {
+    let crabs = group_crabs(&parse(input).map_err(UserError)?);
+    b.bench(|| {
+        find_meeting_point(&crabs, Metric::Triangular)
+            .map(|mp| mp.cost)
+            .ok_or("No crabs!?")
+    })
+}
+
 fn run_parse(input: &str, b: Bench) -> BenchResult {
     b.bench(|| {
         let crabs = parse(input)?;
@@ -45,48 +67,56 @@ fn part2_fuel(n: u32) -> u32 {
     (n * (n + 1)) / 2
 }
 
-#[derive(Debug, Clone, Copy)]
-struct Position {
-    pos: u32,
-    num_crabs: u32,
+/// A crab, or group of crabs, at a point in `D`-dimensional space. The weight is
+/// the fuel efficiency of the crab, or the number of crabs sharing the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position<const D: usize> {
+    pos: [u32; D],
+    weight: u32,
 }
 
-fn find_fuel(crabs: &[u32], fuel_cost: impl Fn(u32) -> u32) -> Result<u32, &'static str> {
-    let mut crab_by_pos: Vec<Position> = Vec::new();
+fn group_crabs(crabs: &[u32]) -> Vec<Position<1>> {
+    let mut crab_by_pos: Vec<Position<1>> = Vec::new();
     for crab in crabs {
-        match crab_by_pos.binary_search_by(|cp| cp.pos.cmp(crab)) {
-            Ok(idx) => crab_by_pos[idx].num_crabs += 1,
+        match crab_by_pos.binary_search_by(|cp| cp.pos[0].cmp(crab)) {
+            Ok(idx) => crab_by_pos[idx].weight += 1,
             Err(idx) => crab_by_pos.insert(
                 idx,
                 Position {
-                    pos: *crab,
-                    num_crabs: 1,
+                    pos: [*crab],
+                    weight: 1,
                 },
             ),
         }
     }
 
-    let last_crab = crab_by_pos.last().unwrap().pos;
+    crab_by_pos
+}
+
+fn find_fuel(crabs: &[u32], fuel_cost: impl Fn(u32) -> u32) -> Result<u32, &'static str> {
+    let crab_by_pos = group_crabs(crabs);
+
+    let last_crab = crab_by_pos.last().unwrap().pos[0];
     let mut cur_idx = 0;
     let mut cur_fuel = u32::MAX;
 
     for pos in 0..=last_crab {
-        if crab_by_pos[cur_idx].pos == pos {
+        if crab_by_pos[cur_idx].pos[0] == pos {
             cur_idx += 1;
         }
 
         let right_fuel = crab_by_pos[cur_idx..]
             .iter()
             .map(|crab_pos| {
-                let n = crab_pos.pos - pos;
-                fuel_cost(n) * crab_pos.num_crabs
+                let n = crab_pos.pos[0] - pos;
+                fuel_cost(n) * crab_pos.weight
             })
             .sum();
         let left_fuel: u32 = crab_by_pos[..cur_idx]
             .iter()
             .map(|crab_pos| {
-                let n = pos - crab_pos.pos;
-                fuel_cost(n) * crab_pos.num_crabs
+                let n = pos - crab_pos.pos[0];
+                fuel_cost(n) * crab_pos.weight
             })
             .sum();
 
@@ -96,12 +126,80 @@ fn find_fuel(crabs: &[u32], fuel_cost: impl Fn(u32) -> u32) -> Result<u32, &'sta
         }
         cur_fuel = total_fuel;
 
-        if crab_by_pos[cur_idx].pos == pos {}
+        if crab_by_pos[cur_idx].pos[0] == pos {}
     }
 
     Err("No minimum fuel!?")
 }
 
+/// How the fuel cost grows with the distance moved along each axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    L1,
+    #[allow(unused)]
+    SquaredL2,
+    Triangular,
+}
+
+impl Metric {
+    fn axis_cost(self, n: u64) -> u64 {
+        match self {
+            Metric::L1 => n,
+            Metric::SquaredL2 => n * n,
+            Metric::Triangular => (n * (n + 1)) / 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MeetingPoint<const D: usize> {
+    pos: [u32; D],
+    cost: u64,
+}
+
+fn axis_fuel<const D: usize>(crabs: &[Position<D>], axis: usize, x: u32, metric: Metric) -> u64 {
+    crabs
+        .iter()
+        .map(|crab| {
+            let n = crab.pos[axis].abs_diff(x) as u64;
+            metric.axis_cost(n) * crab.weight as u64
+        })
+        .sum()
+}
+
+/// Finds the point minimising the total weighted fuel cost.
+///
+/// Every metric is a sum of per-axis costs, so each axis can be solved on its own.
+/// The cost along an axis is convex, so we binary search for where it stops falling.
+fn find_meeting_point<const D: usize>(
+    crabs: &[Position<D>],
+    metric: Metric,
+) -> Option<MeetingPoint<D>> {
+    let mut meeting_point = MeetingPoint {
+        pos: [0; D],
+        cost: 0,
+    };
+
+    for axis in 0..D {
+        let mut low = crabs.iter().map(|c| c.pos[axis]).min()?;
+        let mut high = crabs.iter().map(|c| c.pos[axis]).max()?;
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if axis_fuel(crabs, axis, mid, metric) <= axis_fuel(crabs, axis, mid + 1, metric) {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+
+        meeting_point.pos[axis] = low;
+        meeting_point.cost += axis_fuel(crabs, axis, low, metric);
+    }
+
+    Some(meeting_point)
+}
+
 #[cfg(test)]
 mod tests_template {
     use super::*;
@@ -132,4 +230,59 @@ mod tests_template {
 
         assert_eq!(168, find_fuel(&crabs, part2_fuel).unwrap());
     }
+
+    #[test]
+    fn meeting_point_test() {
+        let input = aoc_lib::input(7).example(Example::Part1, 1).open().unwrap();
+        let crabs = group_crabs(&parse(&input).unwrap());
+
+        let expected = MeetingPoint { pos: [2], cost: 37 };
+        assert_eq!(Some(expected), find_meeting_point(&crabs, Metric::L1));
+
+        let expected = MeetingPoint {
+            pos: [5],
+            cost: 168,
+        };
+        assert_eq!(
+            Some(expected),
+            find_meeting_point(&crabs, Metric::Triangular)
+        );
+    }
+
+    #[test]
+    fn weighted_meeting_point_test() {
+        let crabs = [
+            Position {
+                pos: [0, 0, 0],
+                weight: 1,
+            },
+            Position {
+                pos: [10, 4, 2],
+                weight: 3,
+            },
+            Position {
+                pos: [2, 8, 2],
+                weight: 1,
+            },
+        ];
+
+        let expected = MeetingPoint {
+            pos: [10, 4, 2],
+            cost: 10 + 4 + 2 + 8 + 4,
+        };
+        assert_eq!(Some(expected), find_meeting_point(&crabs, Metric::L1));
+
+        // Weighted means are (32 / 5, 20 / 5, 8 / 5).
+        // Per axis costs are 36 + 3 * 16 + 16, 16 + 16, and 4.
+        let expected = MeetingPoint {
+            pos: [6, 4, 2],
+            cost: 100 + 32 + 4,
+        };
+        assert_eq!(
+            Some(expected),
+            find_meeting_point(&crabs, Metric::SquaredL2)
+        );
+
+        assert_eq!(None, find_meeting_point::<2>(&[], Metric::L1));
+    }
 }
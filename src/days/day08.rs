@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use aoc_lib::{Bench, BenchResult, Day, NoError, ParseResult, UserError};
 use color_eyre::{
     eyre::{eyre, Result},
@@ -9,7 +11,11 @@ pub const DAY: Day = Day {
     name: "Seven Segment Search",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse)],
+    other: &[
+        ("Parse", run_parse),
+        ("Part 2 Solver", run_part2_solver),
+        ("Part 2 Checked", run_part2_checked),
+    ],
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
@@ -29,6 +35,19 @@ fn run_part2(input: &str, b: Bench) -> BenchResult {
     b.bench(|| Ok::<_, NoError>(part2(&data)))
 }
 
+fn run_part2_solver(input: &str, b: Bench) -> BenchResult {
+    let data: Vec<_> = parse(input).map_err(UserError)?;
+    let table = GlyphTable::seven_segment();
+
+    b.bench(|| part2_solver(&table, &data))
+}
+
+fn run_part2_checked(input: &str, b: Bench) -> BenchResult {
+    let data: Vec<_> = parse(input).map_err(UserError)?;
+
+    b.bench(|| part2_checked(&data))
+}
+
 fn run_parse(input: &str, b: Bench) -> BenchResult {
     b.bench(|| {
         let data = parse(input)?;
@@ -85,6 +104,7 @@ fn part1(data: &[Data]) -> u16 {
         .sum()
 }
 
+/// Assumes the signal is valid. Use `try_encode` for unchecked input.
 fn encode(signal: &str) -> u8 {
     let bytes = signal.as_bytes();
     let bytes = &bytes[..bytes.len().min(7)];
@@ -92,8 +112,9 @@ fn encode(signal: &str) -> u8 {
     bytes.iter().map(|b| 1 << (b - b'a')).sum()
 }
 
-fn decode_signals(data: Data) -> u64 {
-    let mut signals = data.signals.map(encode);
+/// Works out which wire drives each segment, `a` to `g`, assuming the signals are
+/// the ten digits.
+fn deduce_segments(mut signals: [u8; 10]) -> [u8; 7] {
     signals.sort_unstable_by_key(|s| s.count_ones());
 
     // Just look for the 1, 4, and 7 cases.
@@ -149,7 +170,13 @@ fn decode_signals(data: Data) -> u64 {
     let f_seg = six_wires & cf_wires;
     let c_seg = cf_wires & !six_wires;
 
-    let digit_map = [
+    [a_seg, b_seg, c_seg, d_seg, e_seg, f_seg, g_seg]
+}
+
+fn build_digit_map(segments: [u8; 7]) -> [u8; 10] {
+    let [a_seg, b_seg, c_seg, d_seg, e_seg, f_seg, g_seg] = segments;
+
+    [
         // 0
         a_seg | b_seg | c_seg | e_seg | f_seg | g_seg,
         // 1
@@ -170,7 +197,11 @@ fn decode_signals(data: Data) -> u64 {
         a_seg | b_seg | c_seg | d_seg | e_seg | f_seg | g_seg,
         // 9
         a_seg | b_seg | c_seg | d_seg | f_seg | g_seg,
-    ];
+    ]
+}
+
+fn decode_signals(data: Data) -> u64 {
+    let digit_map = build_digit_map(deduce_segments(data.signals.map(encode)));
 
     let mut sum = 0;
     for output in data.outputs {
@@ -191,6 +222,375 @@ fn part2(data: &[Data]) -> u64 {
     data.iter().map(|d| decode_signals(*d)).sum()
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryErrorKind {
+    BadSegment(char),
+    DuplicateSegment(char),
+    InconsistentSignals,
+    UnknownOutput,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EntryError {
+    entry: usize,
+    pattern: String,
+    kind: EntryErrorKind,
+}
+
+impl Display for EntryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "entry {}, pattern `{}`: ", self.entry, self.pattern)?;
+        match self.kind {
+            EntryErrorKind::BadSegment(c) => write!(f, "invalid segment {:?}", c),
+            EntryErrorKind::DuplicateSegment(c) => write!(f, "segment {:?} repeated", c),
+            EntryErrorKind::InconsistentSignals => f.write_str("signals are not the ten digits"),
+            EntryErrorKind::UnknownOutput => f.write_str("output is not a digit"),
+        }
+    }
+}
+
+impl std::error::Error for EntryError {}
+
+fn try_encode(signal: &str) -> Result<u8, EntryErrorKind> {
+    let mut wires = 0;
+    for c in signal.chars() {
+        if !('a'..='g').contains(&c) {
+            return Err(EntryErrorKind::BadSegment(c));
+        }
+
+        let wire = 1 << (c as u8 - b'a');
+        if wires & wire != 0 {
+            return Err(EntryErrorKind::DuplicateSegment(c));
+        }
+        wires |= wire;
+    }
+
+    Ok(wires)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DecodedEntry {
+    /// The segment, `a` to `g`, driven by each wire.
+    wire_map: [char; 7],
+    value: u64,
+}
+
+fn decode_entry(entry: usize, data: Data) -> Result<DecodedEntry, EntryError> {
+    let error = |pattern: &str, kind| EntryError {
+        entry,
+        pattern: pattern.to_owned(),
+        kind,
+    };
+
+    let mut signals = [0; 10];
+    for (sig, pattern) in signals.iter_mut().zip(data.signals) {
+        *sig = try_encode(pattern).map_err(|kind| error(pattern, kind))?;
+    }
+
+    // The deduction trusts that the signals are the ten digits, so they need to be
+    // distinct and have the right number of segments.
+    // Index is the number of segments, value is the number of digits with that many.
+    const DIGIT_LENGTHS: [u8; 8] = [0, 0, 1, 1, 1, 3, 3, 1];
+    let mut seen_lengths = [0; 8];
+    for (idx, (sig, pattern)) in signals.iter().zip(data.signals).enumerate() {
+        let len = sig.count_ones() as usize;
+        seen_lengths[len] += 1;
+        if signals[..idx].contains(sig) || seen_lengths[len] > DIGIT_LENGTHS[len] {
+            return Err(error(pattern, EntryErrorKind::InconsistentSignals));
+        }
+    }
+
+    // Even then, we need to check that it found a permutation of the wires, and that
+    // each signal is one of the digits.
+    let segments = deduce_segments(signals);
+    let all_wires = segments.iter().fold(0, |wires, seg| wires | seg);
+    if all_wires != 0x7F || segments.iter().any(|seg| seg.count_ones() != 1) {
+        let pattern = data.signals.join(" ");
+        return Err(error(&pattern, EntryErrorKind::InconsistentSignals));
+    }
+
+    let digit_map = build_digit_map(segments);
+    for (sig, pattern) in signals.iter().zip(data.signals) {
+        if !digit_map.contains(sig) {
+            return Err(error(pattern, EntryErrorKind::InconsistentSignals));
+        }
+    }
+
+    let mut wire_map = ['a'; 7];
+    for (seg, wire) in segments.iter().zip('a'..) {
+        wire_map[seg.trailing_zeros() as usize] = wire;
+    }
+
+    let mut value = 0;
+    for output in data.outputs {
+        let wires = try_encode(output).map_err(|kind| error(output, kind))?;
+        let digit = digit_map
+            .iter()
+            .position(|&map| map == wires)
+            .ok_or_else(|| error(output, EntryErrorKind::UnknownOutput))?;
+
+        value = value * 10 + digit as u64;
+    }
+
+    Ok(DecodedEntry { wire_map, value })
+}
+
+fn part2_checked(data: &[Data]) -> Result<u64, EntryError> {
+    data.iter()
+        .enumerate()
+        .map(|(entry, d)| decode_entry(entry, *d).map(|decoded| decoded.value))
+        .sum()
+}
+
+/// The glyphs a display can show, each given as the set of segments it lights.
+/// Segments are labelled with consecutive letters starting from `a`.
+#[derive(Debug, Clone)]
+struct GlyphTable {
+    num_segments: u32,
+    glyphs: Vec<(char, u32)>,
+}
+
+impl GlyphTable {
+    fn new(num_segments: u32, glyphs: &[(char, &str)]) -> Result<Self> {
+        if !(1..=26).contains(&num_segments) {
+            return Err(eyre!("invalid segment count: {}", num_segments));
+        }
+
+        let mut table = Self {
+            num_segments,
+            glyphs: Vec::new(),
+        };
+
+        for &(glyph, segments) in glyphs {
+            let segments = encode_wires(segments, num_segments)
+                .ok_or_else(|| eyre!("invalid segments for glyph {:?}: {}", glyph, segments))?;
+
+            if table.glyphs.iter().any(|&(_, s)| s == segments) {
+                return Err(eyre!("glyph {:?} duplicates another glyph", glyph));
+            }
+            table.glyphs.push((glyph, segments));
+        }
+
+        Ok(table)
+    }
+
+    fn seven_segment() -> Self {
+        #[rustfmt::skip]
+        let glyphs = [
+            ('0', "abcefg"), ('1', "cf"), ('2', "acdeg"), ('3', "acdfg"), ('4', "bcdf"),
+            ('5', "abdfg"), ('6', "abdefg"), ('7', "acf"), ('8', "abcdefg"), ('9', "abcdfg"),
+        ];
+
+        Self::new(7, &glyphs).unwrap()
+    }
+
+    fn all_segments(&self) -> u32 {
+        (1 << self.num_segments) - 1
+    }
+}
+
+fn encode_wires(pattern: &str, num_wires: u32) -> Option<u32> {
+    pattern.bytes().try_fold(0, |wires, b| {
+        let wire = b.checked_sub(b'a')? as u32;
+        (wire < num_wires && wires & (1 << wire) == 0).then(|| wires | 1 << wire)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WiringError {
+    BadPattern,
+    Inconsistent,
+    Ambiguous,
+}
+
+impl Display for WiringError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WiringError::BadPattern => f.write_str("pattern uses an unknown or repeated wire"),
+            WiringError::Inconsistent => f.write_str("no wiring matches the observed patterns"),
+            WiringError::Ambiguous => {
+                f.write_str("more than one wiring matches the observed patterns")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WiringError {}
+
+/// The segment driven by each wire, stored as a single set bit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Wiring {
+    segments: Vec<u32>,
+}
+
+impl Wiring {
+    fn translate(&self, wires: u32) -> u32 {
+        self.segments
+            .iter()
+            .enumerate()
+            .filter(|(wire, _)| wires & (1 << wire) != 0)
+            .fold(0, |segments, (_, segment)| segments | segment)
+    }
+
+    fn decode(&self, table: &GlyphTable, pattern: &str) -> Option<char> {
+        let segments = self.translate(encode_wires(pattern, table.num_segments)?);
+        table
+            .glyphs
+            .iter()
+            .find(|&&(_, glyph)| glyph == segments)
+            .map(|&(glyph, _)| glyph)
+    }
+}
+
+/// Narrows down the segments each wire could drive until nothing changes.
+/// Returns false if some wire is left with no possible segment.
+fn propagate(table: &GlyphTable, patterns: &[u32], domains: &mut [u32]) -> bool {
+    let all_segments = table.all_segments();
+
+    loop {
+        let mut changed = false;
+
+        // Each pattern has to light one of the glyphs with the same number of segments,
+        // so its lit wires can only drive segments in those glyphs, and its unlit
+        // wires only segments outside them.
+        for &pattern in patterns {
+            let mut lit = 0;
+            let mut unlit = 0;
+
+            for &(_, glyph) in &table.glyphs {
+                if glyph.count_ones() != pattern.count_ones() {
+                    continue;
+                }
+
+                let fits = domains.iter().enumerate().all(|(wire, &domain)| {
+                    let allowed = if pattern & (1 << wire) != 0 {
+                        glyph
+                    } else {
+                        all_segments & !glyph
+                    };
+                    domain & allowed != 0
+                });
+
+                if fits {
+                    lit |= glyph;
+                    unlit |= all_segments & !glyph;
+                }
+            }
+
+            for (wire, domain) in domains.iter_mut().enumerate() {
+                let allowed = if pattern & (1 << wire) != 0 {
+                    lit
+                } else {
+                    unlit
+                };
+                if *domain & !allowed != 0 {
+                    *domain &= allowed;
+                    changed = true;
+                }
+            }
+        }
+
+        // Each segment is driven by exactly one wire.
+        for wire in 0..domains.len() {
+            let segment = domains[wire];
+            if segment.count_ones() != 1 {
+                continue;
+            }
+
+            for (other, domain) in domains.iter_mut().enumerate() {
+                if other != wire && *domain & segment != 0 {
+                    *domain &= !segment;
+                    changed = true;
+                }
+            }
+        }
+
+        if domains.contains(&0) {
+            return false;
+        }
+        if !changed {
+            return true;
+        }
+    }
+}
+
+fn search_wiring(
+    table: &GlyphTable,
+    patterns: &[u32],
+    mut domains: Vec<u32>,
+    solutions: &mut Vec<Wiring>,
+) {
+    // We only need to know whether there's more than one solution.
+    if solutions.len() > 1 || !propagate(table, patterns, &mut domains) {
+        return;
+    }
+
+    let undecided = domains
+        .iter()
+        .enumerate()
+        .filter(|(_, domain)| domain.count_ones() > 1)
+        .min_by_key(|(_, domain)| domain.count_ones());
+
+    let (wire, mut remaining) = match undecided {
+        Some((wire, &domain)) => (wire, domain),
+        None => {
+            solutions.push(Wiring { segments: domains });
+            return;
+        }
+    };
+
+    while remaining != 0 {
+        let segment = remaining & remaining.wrapping_neg();
+        remaining &= !segment;
+
+        let mut next = domains.clone();
+        next[wire] = segment;
+        search_wiring(table, patterns, next, solutions);
+    }
+}
+
+/// Finds the wiring which makes every pattern light up one of the table's glyphs.
+fn solve_wiring(table: &GlyphTable, patterns: &[&str]) -> Result<Wiring, WiringError> {
+    let patterns: Vec<_> = patterns
+        .iter()
+        .map(|p| encode_wires(p, table.num_segments))
+        .collect::<Option<_>>()
+        .ok_or(WiringError::BadPattern)?;
+
+    let domains = vec![table.all_segments(); table.num_segments as usize];
+    let mut solutions = Vec::new();
+    search_wiring(table, &patterns, domains, &mut solutions);
+
+    match solutions.len() {
+        0 => Err(WiringError::Inconsistent),
+        1 => Ok(solutions.pop().unwrap()),
+        _ => Err(WiringError::Ambiguous),
+    }
+}
+
+fn part2_solver(table: &GlyphTable, data: &[Data]) -> Result<u64, WiringError> {
+    let mut sum = 0;
+
+    for d in data {
+        // The outputs are observations too, so they have to be consistent with the wiring.
+        let patterns: Vec<_> = d.signals.iter().chain(&d.outputs).copied().collect();
+        let wiring = solve_wiring(table, &patterns)?;
+
+        let mut value = 0;
+        for output in d.outputs {
+            let digit = wiring
+                .decode(table, output)
+                .and_then(|d| d.to_digit(10))
+                .ok_or(WiringError::Inconsistent)?;
+            value = value * 10 + digit as u64;
+        }
+
+        sum += value;
+    }
+
+    Ok(sum)
+}
+
 #[cfg(test)]
 mod tests_template {
     use super::*;
@@ -237,4 +637,88 @@ mod tests_template {
 
         assert_eq!(61229, part2(&data));
     }
+
+    #[test]
+    fn part2_checked_test() {
+        let input = aoc_lib::input(8).example(Example::Part2, 1).open().unwrap();
+        let data = parse(&input).unwrap();
+
+        let decoded = decode_entry(0, data[0]).unwrap();
+        assert_eq!(5353, decoded.value);
+        assert_eq!(['c', 'f', 'g', 'a', 'b', 'd', 'e'], decoded.wire_map);
+
+        let input = aoc_lib::input(8).example(Example::Part1, 1).open().unwrap();
+        let data = parse(&input).unwrap();
+        assert_eq!(Ok(61229), part2_checked(&data));
+    }
+
+    #[test]
+    fn decode_entry_errors_test() {
+        let input = aoc_lib::input(8).example(Example::Part2, 1).open().unwrap();
+        let mut data = parse(&input).unwrap()[0];
+
+        let check = |data, pattern: &str, kind| {
+            let expected = EntryError {
+                entry: 3,
+                pattern: pattern.to_owned(),
+                kind,
+            };
+            assert_eq!(Err(expected), decode_entry(3, data));
+        };
+
+        data.outputs[1] = "cdfbx";
+        check(data, "cdfbx", EntryErrorKind::BadSegment('x'));
+
+        data.outputs[1] = "cdfbc";
+        check(data, "cdfbc", EntryErrorKind::DuplicateSegment('c'));
+
+        data.outputs[1] = "cdfeg";
+        check(data, "cdfeg", EntryErrorKind::UnknownOutput);
+
+        // Swap the 0 for a repeated 6.
+        data.signals[8] = "cdfgeb";
+        check(data, "cdfgeb", EntryErrorKind::InconsistentSignals);
+
+        // Swap the 0 for a 5-segment pattern.
+        data.signals[8] = "cageb";
+        check(data, "cageb", EntryErrorKind::InconsistentSignals);
+
+        // Right segment counts, but the 6 is now a different 9.
+        data.signals[8] = "cagedb";
+        data.signals[6] = "cefabg";
+        let pattern = data.signals.join(" ");
+        check(data, &pattern, EntryErrorKind::InconsistentSignals);
+    }
+
+    #[test]
+    fn part2_solver_test() {
+        let table = GlyphTable::seven_segment();
+
+        let input = aoc_lib::input(8).example(Example::Part2, 1).open().unwrap();
+        let data = parse(&input).unwrap();
+        assert_eq!(Ok(5353), part2_solver(&table, &data));
+
+        let input = aoc_lib::input(8).example(Example::Part1, 1).open().unwrap();
+        let data = parse(&input).unwrap();
+        assert_eq!(Ok(61229), part2_solver(&table, &data));
+    }
+
+    #[test]
+    fn solver_errors_test() {
+        let table = GlyphTable::seven_segment();
+
+        assert_eq!(Err(WiringError::Ambiguous), solve_wiring(&table, &["ab"]));
+        assert_eq!(
+            Err(WiringError::Inconsistent),
+            solve_wiring(&table, &["ab", "cd"])
+        );
+        assert_eq!(Err(WiringError::BadPattern), solve_wiring(&table, &["abz"]));
+
+        let glyphs = [('A', "a"), ('B', "ab"), ('C', "abc"), ('D', "abcd")];
+        let table = GlyphTable::new(4, &glyphs).unwrap();
+        let wiring = solve_wiring(&table, &["d", "dc", "dcb", "dcba"]).unwrap();
+        assert_eq!(Some('C'), wiring.decode(&table, "bcd"));
+        assert_eq!(Some('A'), wiring.decode(&table, "d"));
+        assert_eq!(None, wiring.decode(&table, "a"));
+    }
 }
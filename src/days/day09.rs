@@ -6,7 +6,11 @@ pub const DAY: Day = Day {
     name: "Smoke Basin",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse)],
+    other: &[
+        ("Parse", run_parse),
+        ("Part 2 Labelled", run_part2_labelled),
+        ("Part 2 Union Find", run_part2_union_find),
+    ],
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
@@ -19,6 +23,16 @@ fn run_part2(input: &str, b: Bench) -> BenchResult {
     b.bench(|| Ok::<_, NoError>(part2(&map)))
 }
 
+fn run_part2_labelled(input: &str, b: Bench) -> BenchResult {
+    let map = Map::parse(input);
+    b.bench(|| Ok::<_, NoError>(part2_labelled(&map)))
+}
+
+fn run_part2_union_find(input: &str, b: Bench) -> BenchResult {
+    let map = Map::parse(input);
+    b.bench(|| Ok::<_, NoError>(part2_union_find(&map)))
+}
+
 fn run_parse(input: &str, b: Bench) -> BenchResult {
     b.bench(|| {
         let map = Map::parse(input);
@@ -50,6 +64,61 @@ impl Map {
             tiles,
         }
     }
+
+    fn basin_sizes_dfs(&self, mut on_basin: impl FnMut(u64)) {
+        let mut basin_map: Vec<_> = self.tiles.iter().map(|&height| height == 9).collect();
+        let mut neighbour_queue = Vec::new();
+
+        for tile_idx in 0..basin_map.len() {
+            if basin_map[tile_idx] {
+                // This tile has either already been counted, or it was a 9.
+                continue;
+            }
+
+            let mut basin_size = 0;
+            neighbour_queue.push(tile_idx);
+
+            while let Some(nb_idx) = neighbour_queue.pop() {
+                if basin_map[nb_idx] {
+                    // This tile has already been seen, or was a 9.
+                    continue;
+                }
+
+                basin_size += 1;
+                basin_map[nb_idx] = true;
+
+                neighbour_queue.extend(
+                    self.neighbours(nb_idx)
+                        .filter(|&nb| unsafe { !*basin_map.get_unchecked(nb) }),
+                );
+            }
+
+            on_basin(basin_size);
+        }
+    }
+
+    fn basin_sizes_union_find(&self, mut on_basin: impl FnMut(u64)) {
+        let mut scanner = BasinScanner::new(self.width);
+        for row in self.tiles.chunks_exact(self.width) {
+            scanner.push_row(row, &mut on_basin);
+        }
+
+        scanner.finish(on_basin);
+    }
+
+    fn neighbours(&self, idx: usize) -> impl Iterator<Item = usize> {
+        let (x, y) = (idx % self.width, idx / self.width);
+        let width = self.width;
+
+        [
+            y.checked_sub(1).map(|y| y * width + x),
+            x.checked_sub(1).map(|x| y * width + x),
+            (x < width - 1).then(|| idx + 1),
+            (y < self.height - 1).then(|| idx + width),
+        ]
+        .into_iter()
+        .flatten()
+    }
 }
 
 fn part1(map: &Map) -> u64 {
@@ -96,69 +165,274 @@ impl Top3Basins {
 }
 
 fn part2(map: &Map) -> u64 {
-    let mut basin_map: Vec<_> = map.tiles.iter().map(|&height| height == 9).collect();
+    let mut basin_sizes = Top3Basins { basins: [0; 3] };
+    map.basin_sizes_dfs(|size| basin_sizes.push(size));
+
+    basin_sizes.basins.into_iter().product()
+}
 
+fn part2_union_find(map: &Map) -> u64 {
     let mut basin_sizes = Top3Basins { basins: [0; 3] };
-    let mut neighbour_queue = Vec::new();
+    map.basin_sizes_union_find(|size| basin_sizes.push(size));
 
-    for tile_idx in 0..basin_map.len() {
-        if basin_map[tile_idx] {
-            // This tile has either already been counted, or it was a 9.
-            continue;
-        }
+    basin_sizes.basins.into_iter().product()
+}
+
+const NO_LABEL: u32 = u32::MAX;
 
-        basin_map[tile_idx] = true;
-        let mut basin_size = 1;
+/// Finds connected basins one row at a time, only keeping the labels of the previous
+/// row. Labels that turn out to be part of the same basin are merged with a union-find.
+///
+/// After each row the labels are renumbered to just the basins that reach it, so
+/// memory stays proportional to the width however many rows there are.
+struct BasinScanner {
+    width: usize,
+    prev_row: Vec<u32>,
+    cur_row: Vec<u32>,
+    parents: Vec<u32>,
+    sizes: Vec<u64>,
+    /// Scratch space for renumbering, kept to save allocating for every row.
+    remap: Vec<u32>,
+    new_sizes: Vec<u64>,
+}
 
-        let (x, y) = (tile_idx % map.width, tile_idx / map.width);
-        match y.checked_sub(1) {
-            Some(y) if !basin_map[y * map.width + x] => neighbour_queue.push(y * map.width + x),
-            _ => {}
+impl BasinScanner {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            prev_row: vec![NO_LABEL; width],
+            cur_row: vec![NO_LABEL; width],
+            parents: Vec::new(),
+            sizes: Vec::new(),
+            remap: Vec::new(),
+            new_sizes: Vec::new(),
         }
-        match x.checked_sub(1) {
-            Some(x) if !basin_map[y * map.width + x] => neighbour_queue.push(y * map.width + x),
-            _ => {}
+    }
+
+    fn find(&mut self, mut label: u32) -> u32 {
+        while self.parents[label as usize] != label {
+            let grandparent = self.parents[self.parents[label as usize] as usize];
+            self.parents[label as usize] = grandparent;
+            label = grandparent;
         }
-        if x < (map.width - 1) && !basin_map[y * map.width + x + 1] {
-            neighbour_queue.push(y * map.width + x + 1);
+
+        label
+    }
+
+    fn union(&mut self, a: u32, b: u32) -> u32 {
+        let (a, b) = (self.find(a), self.find(b));
+        let (root, child) = (a.min(b), a.max(b));
+        self.parents[child as usize] = root;
+
+        root
+    }
+
+    /// Adds the next row, reporting the size of every basin which doesn't reach it.
+    fn push_row(&mut self, row: &[u8], on_basin: impl FnMut(u64)) {
+        assert_eq!(row.len(), self.width, "row width mismatch");
+
+        for (x, &tile) in row.iter().enumerate() {
+            if tile == 9 {
+                self.cur_row[x] = NO_LABEL;
+                continue;
+            }
+
+            let left = x.checked_sub(1).map_or(NO_LABEL, |x| self.cur_row[x]);
+            let up = self.prev_row[x];
+
+            let label = match (left, up) {
+                (NO_LABEL, NO_LABEL) => {
+                    let label = self.parents.len() as u32;
+                    self.parents.push(label);
+                    self.sizes.push(0);
+                    label
+                }
+                (label, NO_LABEL) | (NO_LABEL, label) => label,
+                (left, up) => self.union(left, up),
+            };
+
+            self.sizes[label as usize] += 1;
+            self.cur_row[x] = label;
         }
-        if y < (map.height - 1) && !basin_map[(y + 1) * map.width + x] {
-            neighbour_queue.push((y + 1) * map.width + x);
+
+        self.compact(on_basin);
+        std::mem::swap(&mut self.prev_row, &mut self.cur_row);
+    }
+
+    /// Sizes were counted against whichever label a tile got at the time, so need
+    /// moving to the root label.
+    fn gather_sizes(&mut self) {
+        for label in (0..self.parents.len() as u32).rev() {
+            let root = self.find(label);
+            if root != label {
+                self.sizes[root as usize] += std::mem::take(&mut self.sizes[label as usize]);
+            }
         }
+    }
+
+    /// Reports the basins the current row doesn't touch, since they can't grow any
+    /// more, and renumbers the rest from zero in the order the row reaches them.
+    fn compact(&mut self, mut on_basin: impl FnMut(u64)) {
+        self.gather_sizes();
 
-        while let Some(nb_idx) = neighbour_queue.pop() {
-            if basin_map[nb_idx] {
-                // This tile has already been seen, or was a 9.
+        self.remap.clear();
+        self.remap.resize(self.parents.len(), NO_LABEL);
+        self.new_sizes.clear();
+        for x in 0..self.width {
+            if self.cur_row[x] == NO_LABEL {
                 continue;
             }
 
-            basin_size += 1;
-            basin_map[nb_idx] = true;
-
-            let (x, y) = (nb_idx % map.width, nb_idx / map.width);
+            let root = self.find(self.cur_row[x]) as usize;
+            if self.remap[root] == NO_LABEL {
+                self.remap[root] = self.new_sizes.len() as u32;
+                self.new_sizes.push(self.sizes[root]);
+            }
+            self.cur_row[x] = self.remap[root];
+        }
 
-            match y.checked_sub(1) {
-                Some(y) if unsafe { !*basin_map.get_unchecked(y * map.width + x) } => {
-                    neighbour_queue.push(y * map.width + x)
-                }
-                _ => {}
+        for (label, &size) in self.sizes.iter().enumerate() {
+            if self.parents[label] == label as u32 && self.remap[label] == NO_LABEL {
+                on_basin(size);
             }
-            match x.checked_sub(1) {
-                Some(x) if unsafe { !*basin_map.get_unchecked(y * map.width + x) } => {
-                    neighbour_queue.push(y * map.width + x)
-                }
-                _ => {}
+        }
+
+        std::mem::swap(&mut self.sizes, &mut self.new_sizes);
+        self.parents.clear();
+        self.parents.extend(0..self.sizes.len() as u32);
+    }
+
+    fn finish(mut self, mut on_basin: impl FnMut(u64)) {
+        self.gather_sizes();
+
+        for (label, &size) in self.sizes.iter().enumerate() {
+            if self.parents[label] == label as u32 {
+                on_basin(size);
             }
-            if x < (map.width - 1) && unsafe { !*basin_map.get_unchecked(y * map.width + x + 1) } {
-                neighbour_queue.push(y * map.width + x + 1);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Basin {
+    low_point: (usize, usize),
+    size: u64,
+    // Inclusive bounding box.
+    top_left: (usize, usize),
+    bottom_right: (usize, usize),
+}
+
+struct BasinLabels {
+    width: usize,
+    /// The basin ID of each tile, or `None` for the 9s between basins.
+    labels: Vec<Option<u32>>,
+    /// The tile index of the low point reached by following the steepest descent
+    /// from each tile. Where that runs into a plateau with nothing lower next to it,
+    /// there's no steepest way on, so the tile drains to its basin's low point.
+    drains_to: Vec<Option<usize>>,
+    basins: Vec<Basin>,
+}
+
+impl BasinLabels {
+    #[allow(unused)]
+    fn basin_at(&self, x: usize, y: usize) -> Option<&Basin> {
+        if x >= self.width {
+            return None;
+        }
+
+        let id = (*self.labels.get(y * self.width + x)?)?;
+        Some(&self.basins[id as usize])
+    }
+
+    #[allow(unused)]
+    fn drains_to(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        if x >= self.width {
+            return None;
+        }
+
+        let idx = (*self.drains_to.get(y * self.width + x)?)?;
+        Some((idx % self.width, idx / self.width))
+    }
+}
+
+fn label_basins(map: &Map) -> BasinLabels {
+    let mut labels = vec![None; map.tiles.len()];
+    let mut basins = Vec::new();
+    let mut queue = Vec::new();
+
+    for start in 0..map.tiles.len() {
+        if map.tiles[start] == 9 || labels[start].is_some() {
+            continue;
+        }
+
+        let id = basins.len() as u32;
+        let (x, y) = (start % map.width, start / map.width);
+        let mut low_point = start;
+        let mut basin = Basin {
+            low_point: (x, y),
+            size: 0,
+            top_left: (x, y),
+            bottom_right: (x, y),
+        };
+
+        labels[start] = Some(id);
+        queue.push(start);
+
+        while let Some(idx) = queue.pop() {
+            let (x, y) = (idx % map.width, idx / map.width);
+            basin.size += 1;
+            basin.top_left = (basin.top_left.0.min(x), basin.top_left.1.min(y));
+            basin.bottom_right = (basin.bottom_right.0.max(x), basin.bottom_right.1.max(y));
+
+            // Ties go to the first tile in reading order.
+            if (map.tiles[idx], idx) < (map.tiles[low_point], low_point) {
+                low_point = idx;
             }
-            if y < (map.height - 1) && unsafe { !*basin_map.get_unchecked((y + 1) * map.width + x) }
-            {
-                neighbour_queue.push((y + 1) * map.width + x);
+
+            for nb in map.neighbours(idx) {
+                if map.tiles[nb] != 9 && labels[nb].is_none() {
+                    labels[nb] = Some(id);
+                    queue.push(nb);
+                }
             }
         }
 
-        basin_sizes.push(basin_size);
+        basin.low_point = (low_point % map.width, low_point / map.width);
+        basins.push(basin);
+    }
+
+    // A tile can only drain to a lower tile, so by working up from the lowest tiles
+    // we know where the next tile down drains to.
+    let mut drains_to = vec![None; map.tiles.len()];
+    for height in 0..9 {
+        for idx in (0..map.tiles.len()).filter(|&idx| map.tiles[idx] == height) {
+            let lowest = map.neighbours(idx).min_by_key(|&nb| map.tiles[nb]);
+            drains_to[idx] = match lowest {
+                Some(nb) if map.tiles[nb] < height => drains_to[nb],
+                Some(nb) if map.tiles[nb] == height => {
+                    let basin = &basins[labels[idx].unwrap() as usize];
+                    let (x, y) = basin.low_point;
+                    Some(y * map.width + x)
+                }
+                _ => Some(idx),
+            };
+        }
+    }
+
+    BasinLabels {
+        width: map.width,
+        labels,
+        drains_to,
+        basins,
+    }
+}
+
+fn part2_labelled(map: &Map) -> u64 {
+    let labels = label_basins(map);
+
+    let mut basin_sizes = Top3Basins { basins: [0; 3] };
+    for basin in &labels.basins {
+        basin_sizes.push(basin.size);
     }
 
     basin_sizes.basins.into_iter().product()
@@ -186,4 +460,124 @@ mod tests_template {
 
         assert_eq!(1134, part2(&map));
     }
+
+    #[test]
+    fn part2_union_find_test() {
+        let input = aoc_lib::input(9).example(Example::Part1, 1).open().unwrap();
+
+        let map = Map::parse(&input);
+        assert_eq!(1134, part2_union_find(&map));
+
+        let mut dfs_sizes = Vec::new();
+        map.basin_sizes_dfs(|size| dfs_sizes.push(size));
+        let mut union_find_sizes = Vec::new();
+        map.basin_sizes_union_find(|size| union_find_sizes.push(size));
+
+        dfs_sizes.sort_unstable();
+        union_find_sizes.sort_unstable();
+        assert_eq!(dfs_sizes, union_find_sizes);
+
+        // Two labels that only meet on a later row.
+        let mut sizes = Vec::new();
+        let mut scanner = BasinScanner::new(5);
+        scanner.push_row(&[1, 9, 2, 9, 3], |size| sizes.push(size));
+        scanner.push_row(&[1, 9, 2, 9, 3], |size| sizes.push(size));
+        scanner.push_row(&[1, 1, 1, 9, 3], |size| sizes.push(size));
+        assert!(sizes.is_empty());
+
+        scanner.finish(|size| sizes.push(size));
+        assert_eq!(vec![7, 3], sizes);
+
+        // Lots of tiny basins, each finished a couple of rows after it starts.
+        let width = 100;
+        let mut scanner = BasinScanner::new(width);
+        let mut count = 0;
+        for y in 0..1000 {
+            let row: Vec<_> = (0..width)
+                .map(|x| if (x + y) % 2 == 0 && y % 3 != 2 { 1 } else { 9 })
+                .collect();
+            scanner.push_row(&row, |size| {
+                assert_eq!(1, size);
+                count += 1;
+            });
+            assert!(scanner.parents.len() <= width);
+            assert!(scanner.sizes.len() <= width);
+        }
+        scanner.finish(|_| count += 1);
+        let expected = map_from_fn(width, 1000, |x, y| (x + y) % 2 == 0 && y % 3 != 2);
+        assert_eq!(expected.tiles.iter().filter(|&&t| t != 9).count(), count);
+
+        // A scattering of walls, which joins basins up in all sorts of orders.
+        let mut seed = 12345_u32;
+        let map = map_from_fn(60, 80, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % 10 < 6
+        });
+        let mut dfs_sizes = Vec::new();
+        map.basin_sizes_dfs(|size| dfs_sizes.push(size));
+        let mut union_find_sizes = Vec::new();
+        map.basin_sizes_union_find(|size| union_find_sizes.push(size));
+
+        dfs_sizes.sort_unstable();
+        union_find_sizes.sort_unstable();
+        assert_eq!(dfs_sizes, union_find_sizes);
+    }
+
+    /// A map with a 1 wherever `open` says so, and 9s everywhere else.
+    fn map_from_fn(width: usize, height: usize, mut open: impl FnMut(usize, usize) -> bool) -> Map {
+        let tiles = (0..width * height)
+            .map(|idx| if open(idx % width, idx / width) { 1 } else { 9 })
+            .collect();
+
+        Map {
+            tiles,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn label_basins_test() {
+        let input = aoc_lib::input(9).example(Example::Part1, 1).open().unwrap();
+
+        let map = Map::parse(&input);
+        let labels = label_basins(&map);
+        assert_eq!(1134, part2_labelled(&map));
+
+        let sizes: Vec<_> = labels.basins.iter().map(|b| b.size).collect();
+        assert_eq!(vec![3, 9, 14, 9], sizes);
+
+        let expected = Basin {
+            low_point: (1, 0),
+            size: 3,
+            top_left: (0, 0),
+            bottom_right: (1, 1),
+        };
+        assert_eq!(Some(&expected), labels.basin_at(0, 1));
+        assert_eq!(None, labels.basin_at(2, 0));
+        assert_eq!(None, labels.basin_at(10, 0));
+
+        let expected = Basin {
+            low_point: (2, 2),
+            size: 14,
+            top_left: (0, 1),
+            bottom_right: (5, 4),
+        };
+        assert_eq!(Some(&expected), labels.basin_at(3, 3));
+
+        assert_eq!(Some((1, 0)), labels.drains_to(0, 1));
+        assert_eq!(Some((6, 4)), labels.drains_to(9, 4));
+        assert_eq!(None, labels.drains_to(4, 0));
+
+        // The 2s are level with each other, so the left one can only get down to the 1
+        // by crossing the plateau, and the flat bottom of the right basin drains to its
+        // first tile.
+        let map = Map::parse("99999999\n92299339\n93199339\n99999999");
+        let labels = label_basins(&map);
+        assert_eq!(Some((2, 2)), labels.drains_to(1, 1));
+        assert_eq!(Some((2, 2)), labels.drains_to(2, 1));
+        assert_eq!(Some((2, 2)), labels.drains_to(1, 2));
+        assert_eq!(Some((5, 1)), labels.drains_to(6, 2));
+        assert_eq!(Some((5, 1)), labels.drains_to(5, 1));
+    }
 }
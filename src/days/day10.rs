@@ -1,5 +1,10 @@
-use aoc_lib::{Bench, BenchResult, Day, NoError, ParseResult};
-use color_eyre::Report;
+use std::fmt::Write;
+
+use aoc_lib::{Bench, BenchResult, Day, ParseResult};
+use color_eyre::{
+    eyre::{eyre, Context, Result},
+    Report,
+};
 
 // 7:36
 // 8:13
@@ -10,17 +15,25 @@ pub const DAY: Day = Day {
     name: "Syntax Scoring",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse)],
+    other: &[("Parse", run_parse), ("Report", run_report)],
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
     let lines: Vec<_> = input.lines().collect();
-    b.bench(|| Ok::<_, NoError>(part1(&lines)))
+    let lang = BracketLanguage::aoc();
+    b.bench(|| part1(&lang, &lines))
 }
 
 fn run_part2(input: &str, b: Bench) -> BenchResult {
     let lines: Vec<_> = input.lines().collect();
-    b.bench(|| Ok::<_, NoError>(part2(&lines)))
+    let lang = BracketLanguage::aoc();
+    b.bench(|| part2(&lang, &lines))
+}
+
+fn run_report(input: &str, b: Bench) -> BenchResult {
+    let lines: Vec<_> = input.lines().collect();
+    let lang = BracketLanguage::aoc();
+    b.bench_alt(|| render_report(&lang, &lines))
 }
 
 fn run_parse(input: &str, b: Bench) -> BenchResult {
@@ -30,91 +43,296 @@ fn run_parse(input: &str, b: Bench) -> BenchResult {
     })
 }
 
-fn bracket_score(byte: u8) -> u64 {
-    match byte {
-        b')' => 3,
-        b']' => 57,
-        b'}' => 1197,
-        b'>' => 25137,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BracketPair {
+    open: char,
+    close: char,
+    corrupted_score: u64,
+    completion_score: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ignore {
+    Chars(Vec<char>),
+    #[allow(unused)]
+    AllOther,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Open(usize),
+    Close(usize),
+    Ignored,
+}
+
+/// Defines which characters open and close brackets, how they're scored, and which
+/// characters can appear between them.
+#[derive(Debug, Clone)]
+struct BracketLanguage {
+    pairs: Vec<BracketPair>,
+    ignore: Ignore,
+}
+
+impl BracketLanguage {
+    fn new(pairs: Vec<BracketPair>, ignore: Ignore) -> Result<Self> {
+        let mut seen = Vec::new();
+        for pair in &pairs {
+            for c in [pair.open, pair.close] {
+                if seen.contains(&c) {
+                    return Err(eyre!("bracket {:?} used more than once", c));
+                }
+                if matches!(&ignore, Ignore::Chars(chars) if chars.contains(&c)) {
+                    return Err(eyre!("bracket {:?} is also ignored", c));
+                }
+                seen.push(c);
+            }
+        }
 
-        b'(' => 1,
-        b'[' => 2,
-        b'{' => 3,
-        b'<' => 4,
-        _ => 0,
+        Ok(Self { pairs, ignore })
+    }
+
+    fn aoc() -> Self {
+        #[rustfmt::skip]
+        let pairs = [
+            ('(', ')', 3, 1),
+            ('[', ']', 57, 2),
+            ('{', '}', 1197, 3),
+            ('<', '>', 25137, 4),
+        ];
+
+        let pairs = pairs
+            .into_iter()
+            .map(
+                |(open, close, corrupted_score, completion_score)| BracketPair {
+                    open,
+                    close,
+                    corrupted_score,
+                    completion_score,
+                },
+            )
+            .collect();
+
+        Self::new(pairs, Ignore::Chars(Vec::new())).unwrap()
+    }
+
+    fn pair(&self, c: char) -> Option<&BracketPair> {
+        self.pairs.iter().find(|p| p.open == c || p.close == c)
+    }
+
+    fn classify(&self, c: char) -> Option<Token> {
+        for (idx, pair) in self.pairs.iter().enumerate() {
+            if c == pair.open {
+                return Some(Token::Open(idx));
+            }
+            if c == pair.close {
+                return Some(Token::Close(idx));
+            }
+        }
+
+        match &self.ignore {
+            Ignore::AllOther => Some(Token::Ignored),
+            Ignore::Chars(chars) if chars.contains(&c) => Some(Token::Ignored),
+            Ignore::Chars(_) => None,
+        }
     }
 }
 
-fn get_open(byte: u8) -> u8 {
-    match byte {
-        b')' => b'(',
-        b']' => b'[',
-        b'}' => b'{',
-        b'>' => b'<',
-        _ => unreachable!(),
+/// The result of checking a single line. Columns are zero-based character indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LineStatus {
+    Valid,
+    Corrupted {
+        column: usize,
+        expected: char,
+        found: char,
+    },
+    Incomplete {
+        completion: String,
+    },
+    UnexpectedClose {
+        column: usize,
+    },
+}
+
+fn analyze_line(lang: &BracketLanguage, line: &str, stack: &mut Vec<usize>) -> Result<LineStatus> {
+    stack.clear();
+
+    for (column, c) in line.chars().enumerate() {
+        match lang.classify(c) {
+            Some(Token::Open(pair)) => stack.push(pair),
+            Some(Token::Close(pair)) => match stack.pop() {
+                None => return Ok(LineStatus::UnexpectedClose { column }),
+                Some(prev) if prev != pair => {
+                    return Ok(LineStatus::Corrupted {
+                        column,
+                        expected: lang.pairs[prev].close,
+                        found: c,
+                    })
+                }
+                Some(_) => {}
+            },
+            Some(Token::Ignored) => {}
+            None => return Err(eyre!("column {}: unexpected character {:?}", column + 1, c)),
+        }
+    }
+
+    if stack.is_empty() {
+        Ok(LineStatus::Valid)
+    } else {
+        let completion = stack.iter().rev().map(|&pair| lang.pairs[pair].close);
+        Ok(LineStatus::Incomplete {
+            completion: completion.collect(),
+        })
     }
 }
 
-fn part1(input: &[&str]) -> u64 {
+fn part1(lang: &BracketLanguage, input: &[&str]) -> Result<u64> {
     let mut stack = Vec::new();
     let mut score = 0;
 
-    for line in input.iter() {
-        stack.clear();
-
-        for byte in line.bytes() {
-            match byte {
-                b'(' | b'[' | b'{' | b'<' => stack.push(byte),
-                b')' | b']' | b'}' | b'>' => {
-                    if stack.is_empty() {
-                        // incomplete?
-                        break;
-                    }
-                    let prev = stack.pop().unwrap();
-                    if prev != get_open(byte) {
-                        // Corrupted line.
-                        score += bracket_score(byte);
-                        break;
-                    }
-                }
-                _ => panic!("bad input"),
-            }
+    for (line_idx, line) in input.iter().enumerate() {
+        let status =
+            analyze_line(lang, line, &mut stack).with_context(|| eyre!("line {}", line_idx + 1))?;
+
+        if let LineStatus::Corrupted { found, .. } = status {
+            score += lang.pair(found).map_or(0, |p| p.corrupted_score);
         }
     }
 
-    score
+    Ok(score)
 }
 
-fn part2(input: &[&str]) -> u64 {
+fn part2(lang: &BracketLanguage, input: &[&str]) -> Result<u64> {
     let mut stack = Vec::new();
     let mut scores = Vec::new();
 
-    'outer: for line in input.iter() {
-        stack.clear();
-        for byte in line.bytes() {
-            match byte {
-                b'(' | b'[' | b'{' | b'<' => stack.push(byte),
-                b')' | b']' | b'}' | b'>' => {
-                    let prev = stack.pop().expect("empty stack");
-                    if prev != get_open(byte) {
-                        // Corrupted line.
-                        continue 'outer;
-                    }
-                }
-                _ => panic!("bad input"),
+    for (line_idx, line) in input.iter().enumerate() {
+        let status =
+            analyze_line(lang, line, &mut stack).with_context(|| eyre!("line {}", line_idx + 1))?;
+
+        if let LineStatus::Incomplete { completion } = status {
+            let mut score = 0;
+            for close in completion.chars() {
+                score *= 5;
+                score += lang.pair(close).map_or(0, |p| p.completion_score);
             }
+            scores.push(score);
         }
+    }
 
-        let mut score = 0;
-        for &open in stack.iter().rev() {
-            score *= 5;
-            score += bracket_score(open);
-        }
-        scores.push(score);
+    if scores.is_empty() {
+        return Err(eyre!("no incomplete lines"));
     }
 
     scores.sort_unstable();
-    scores[scores.len() / 2]
+    Ok(scores[scores.len() / 2])
+}
+
+/// Fixes a line by replacing wrong closers, removing unmatched closers, and then
+/// closing anything left open.
+#[allow(unused)]
+fn repair_line(lang: &BracketLanguage, line: &str) -> Result<String> {
+    let mut stack = Vec::new();
+    let mut line: Vec<char> = line.chars().collect();
+
+    // Every fix is further along the line than the last, so this will finish.
+    loop {
+        let fixed: String = line.iter().collect();
+        match analyze_line(lang, &fixed, &mut stack)? {
+            LineStatus::Valid => return Ok(fixed),
+            LineStatus::Incomplete { completion } => return Ok(fixed + &completion),
+            LineStatus::Corrupted {
+                column, expected, ..
+            } => line[column] = expected,
+            LineStatus::UnexpectedClose { column } => {
+                line.remove(column);
+            }
+        }
+    }
+}
+
+fn render_diagnostic(
+    output: &mut String,
+    gutter_width: usize,
+    line_idx: usize,
+    line: &str,
+    column: usize,
+    title: &str,
+    label: &str,
+) -> std::fmt::Result {
+    let line_no = line_idx + 1;
+
+    writeln!(output, "error: {}", title)?;
+    writeln!(
+        output,
+        "{:>w$}--> line {}, column {}",
+        "",
+        line_no,
+        column + 1,
+        w = gutter_width
+    )?;
+    writeln!(output, "{:>w$} |", "", w = gutter_width)?;
+    writeln!(output, "{:>w$} | {}", line_no, line, w = gutter_width)?;
+    writeln!(
+        output,
+        "{:>w$} | {:>c$}^ {}",
+        "",
+        "",
+        label,
+        w = gutter_width,
+        c = column
+    )?;
+    writeln!(output)
+}
+
+/// Renders every line with a problem, with a caret pointing at where it went wrong.
+fn render_report(lang: &BracketLanguage, input: &[&str]) -> Result<String> {
+    let mut stack = Vec::new();
+    let mut output = String::new();
+    let gutter_width = input.len().to_string().len();
+
+    for (line_idx, line) in input.iter().enumerate() {
+        let status =
+            analyze_line(lang, line, &mut stack).with_context(|| eyre!("line {}", line_idx + 1))?;
+
+        let (column, title, label) = match status {
+            LineStatus::Valid => continue,
+            LineStatus::Corrupted {
+                column,
+                expected,
+                found,
+            } => (
+                column,
+                format!("expected `{}`, found `{}`", expected, found),
+                format!("expected `{}`", expected),
+            ),
+            LineStatus::Incomplete { completion } => (
+                line.chars().count(),
+                "incomplete line".to_owned(),
+                format!("missing `{}`", completion),
+            ),
+            LineStatus::UnexpectedClose { column } => (
+                column,
+                format!(
+                    "unexpected `{}`",
+                    line.chars().nth(column).unwrap_or_default()
+                ),
+                "nothing to close".to_owned(),
+            ),
+        };
+
+        render_diagnostic(
+            &mut output,
+            gutter_width,
+            line_idx,
+            line,
+            column,
+            &title,
+            &label,
+        )?;
+    }
+
+    Ok(output)
 }
 
 #[cfg(test)]
@@ -131,7 +349,7 @@ mod tests_template {
 
         let lines: Vec<_> = input.lines().collect();
 
-        assert_eq!(26397, part1(&lines));
+        assert_eq!(26397, part1(&BracketLanguage::aoc(), &lines).unwrap());
     }
 
     #[test]
@@ -143,6 +361,119 @@ mod tests_template {
 
         let lines: Vec<_> = input.lines().collect();
 
-        assert_eq!(288957, part2(&lines));
+        assert_eq!(288957, part2(&BracketLanguage::aoc(), &lines).unwrap());
+    }
+
+    #[test]
+    fn analyze_line_test() {
+        let lang = BracketLanguage::aoc();
+        let mut stack = Vec::new();
+
+        let expected = LineStatus::Corrupted {
+            column: 12,
+            expected: ']',
+            found: '}',
+        };
+        let actual = analyze_line(&lang, "{([(<{}[<>[]}>{[]{[(<()>", &mut stack).unwrap();
+        assert_eq!(expected, actual);
+
+        let expected = LineStatus::Incomplete {
+            completion: "}}]])})]".to_owned(),
+        };
+        let actual = analyze_line(&lang, "[({(<(())[]>[[{[]{<()<>>", &mut stack).unwrap();
+        assert_eq!(expected, actual);
+
+        let expected = LineStatus::UnexpectedClose { column: 2 };
+        assert_eq!(expected, analyze_line(&lang, "()]", &mut stack).unwrap());
+        assert_eq!(
+            LineStatus::Valid,
+            analyze_line(&lang, "([])", &mut stack).unwrap()
+        );
+        assert!(analyze_line(&lang, "(x)", &mut stack).is_err());
+
+        // Early closes don't score, or stop the completion score.
+        let lines = ["()]", "{([(<{}[<>[]}>{[]{[(<()>", "[<"];
+        assert_eq!(1197, part1(&lang, &lines).unwrap());
+        assert_eq!(22, part2(&lang, &lines).unwrap());
+    }
+
+    #[test]
+    fn repair_line_test() {
+        let lang = BracketLanguage::aoc();
+
+        assert_eq!("([])", repair_line(&lang, "([])").unwrap());
+        assert_eq!("([{}])", repair_line(&lang, "([{").unwrap());
+        assert_eq!("{([])}", repair_line(&lang, "{([)]").unwrap());
+        assert_eq!("()", repair_line(&lang, "())").unwrap());
+        assert_eq!("()[]", repair_line(&lang, ")(][").unwrap());
+    }
+
+    #[test]
+    fn render_report_test() {
+        let lang = BracketLanguage::aoc();
+        let lines = [
+            "()",
+            "{([(<{}[<>[]}>{[]{[(<()>",
+            "[<",
+            "",
+            "",
+            "",
+            "",
+            "",
+            "",
+            "())",
+        ];
+
+        let expected = "\
+error: expected `]`, found `}`
+  --> line 2, column 13
+   |
+ 2 | {([(<{}[<>[]}>{[]{[(<()>
+   |             ^ expected `]`
+
+error: incomplete line
+  --> line 3, column 3
+   |
+ 3 | [<
+   |   ^ missing `>]`
+
+error: unexpected `)`
+  --> line 10, column 3
+   |
+10 | ())
+   |   ^ nothing to close
+
+";
+        assert_eq!(expected, render_report(&lang, &lines).unwrap());
+    }
+
+    #[test]
+    fn custom_language_test() {
+        let pairs = vec![
+            BracketPair {
+                open: '«',
+                close: '»',
+                corrupted_score: 10,
+                completion_score: 1,
+            },
+            BracketPair {
+                open: '(',
+                close: ')',
+                corrupted_score: 100,
+                completion_score: 2,
+            },
+        ];
+
+        let lang = BracketLanguage::new(pairs.clone(), Ignore::AllOther).unwrap();
+        let lines = ["call(«name») ok", "call(«name)»", "«if (x) «y", "(«"];
+        assert_eq!(100, part1(&lang, &lines).unwrap());
+        // Completion scores are 1 * 5 + 1 = 6, and 1 * 5 + 2 = 7.
+        assert_eq!(7, part2(&lang, &lines[2..]).unwrap());
+
+        let lang = BracketLanguage::new(pairs.clone(), Ignore::Chars(vec![' '])).unwrap();
+        assert!(part1(&lang, &["( «x»)"]).is_err());
+        assert_eq!(0, part1(&lang, &["( « » )"]).unwrap());
+
+        assert!(BracketLanguage::new(pairs, Ignore::Chars(vec!['»'])).is_err());
     }
 }
@@ -1,3 +1,5 @@
+use std::{collections::HashMap, fmt::Write};
+
 use aoc_lib::{Bench, BenchResult, Day, NoError, ParseResult, UserError};
 use color_eyre::{eyre::Result, Report};
 
@@ -10,7 +12,7 @@ pub const DAY: Day = Day {
     name: "Dumbo Octopus",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse)],
+    other: &[("Parse", run_parse), ("Animation", run_animation)],
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
@@ -31,19 +33,18 @@ fn run_part1(input: &str, b: Bench) -> BenchResult {
 fn run_part2(input: &str, b: Bench) -> BenchResult {
     let field = Field::parse(input.trim()).map_err(UserError)?;
     b.bench(|| {
-        let mut field = field.clone();
-        let mut step_queue = Vec::new();
-        let mut flash_step = 0;
-        for i in 1..=5000 {
-            if field.step(&mut step_queue) == field.tiles.len() as u64 {
-                flash_step = i;
-                break;
-            }
-        }
-        Ok::<_, NoError>(flash_step)
+        Simulator::new(field.clone())
+            .find_sync()
+            .first_sync
+            .ok_or("octopuses never synchronise")
     })
 }
 
+fn run_animation(input: &str, b: Bench) -> BenchResult {
+    let field = Field::parse(input.trim()).map_err(UserError)?;
+    b.bench_alt(|| Ok::<_, NoError>(animate_ansi(&field, 20)))
+}
+
 fn run_parse(input: &str, b: Bench) -> BenchResult {
     b.bench(|| {
         let field = Field::parse(input)?;
@@ -58,33 +59,105 @@ struct Field {
     height: usize,
 }
 
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Neighbourhood {
+    VonNeumann,
+    Moore,
+    /// Odd rows are shifted half a tile to the right.
+    Hex,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Boundary {
+    Edges,
+    Wrap,
+}
+
+/// The rules of the cascade. A step raises every octopus by `increment`, each flash
+/// raises its neighbours by `spread`, and anything reaching `threshold` flashes once
+/// before being set to `reset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rules {
+    threshold: u8,
+    increment: u8,
+    spread: u8,
+    reset: u8,
+    neighbourhood: Neighbourhood,
+    boundary: Boundary,
+}
+
+impl Rules {
+    const AOC: Rules = Rules {
+        threshold: 10,
+        increment: 1,
+        spread: 1,
+        reset: 0,
+        neighbourhood: Neighbourhood::Moore,
+        boundary: Boundary::Edges,
+    };
+}
+
 #[inline(always)]
-fn add_neighbours(check_queue: &mut Vec<usize>, idx: usize, width: usize, height: usize) {
+fn add_neighbours(
+    check_queue: &mut Vec<usize>,
+    idx: usize,
+    width: usize,
+    height: usize,
+    neighbourhood: Neighbourhood,
+    boundary: Boundary,
+) {
     let x = (idx % width) as isize;
     let y = (idx / width) as isize;
     let iwidth = width as isize;
     let iheight = height as isize;
+
     #[rustfmt::skip]
-    let rel: [(isize, isize); 8] = [
+    const MOORE: [(isize, isize); 8] = [
         (-1, -1), ( 0, -1), ( 1, -1),
         (-1,  0),           ( 1,  0),
         (-1,  1), ( 0,  1), ( 1,  1)
     ];
+    #[rustfmt::skip]
+    const VON_NEUMANN: [(isize, isize); 4] = [
+                  ( 0, -1),
+        (-1,  0),           ( 1,  0),
+                  ( 0,  1),
+    ];
+    #[rustfmt::skip]
+    const HEX_EVEN: [(isize, isize); 6] = [
+        (-1, -1), ( 0, -1),
+        (-1,  0),           ( 1,  0),
+        (-1,  1), ( 0,  1),
+    ];
+    #[rustfmt::skip]
+    const HEX_ODD: [(isize, isize); 6] = [
+                  ( 0, -1), ( 1, -1),
+        (-1,  0),           ( 1,  0),
+                  ( 0,  1), ( 1,  1),
+    ];
 
-    for (rx, ry) in rel {
-        let new_x = match (x, rx) {
-            (0, -1) => continue,
-            (_, 1) if x == iwidth - 1 => continue,
-            (x, rx) => (x + rx) as usize,
-        };
-
-        let new_y = match (y, ry) {
-            (0, -1) => continue,
-            (_, 1) if y == iheight - 1 => continue,
-            (y, ry) => (y + ry) as usize,
+    let rel: &[(isize, isize)] = match neighbourhood {
+        Neighbourhood::VonNeumann => &VON_NEUMANN,
+        Neighbourhood::Moore => &MOORE,
+        Neighbourhood::Hex if y % 2 == 0 => &HEX_EVEN,
+        Neighbourhood::Hex => &HEX_ODD,
+    };
+
+    for &(rx, ry) in rel {
+        let (new_x, new_y) = (x + rx, y + ry);
+        let (new_x, new_y) = match boundary {
+            Boundary::Edges => {
+                if !(0..iwidth).contains(&new_x) || !(0..iheight).contains(&new_y) {
+                    continue;
+                }
+                (new_x, new_y)
+            }
+            Boundary::Wrap => (new_x.rem_euclid(iwidth), new_y.rem_euclid(iheight)),
         };
 
-        check_queue.push(new_y * width + new_x);
+        check_queue.push(new_y as usize * width + new_x as usize);
     }
 }
 
@@ -108,6 +181,8 @@ impl Field {
         })
     }
 
+    /// The top bit of each tile marks a flash, so energy levels must stay below 128.
+    /// `Simulator` doesn't have this limit.
     fn step(&mut self, check_queue: &mut Vec<usize>) -> u64 {
         self.tiles.iter_mut().for_each(|t| *t += 1);
 
@@ -118,7 +193,14 @@ impl Field {
             }
 
             self.tiles[idx] |= 0x80; // Mark it as flashed.
-            add_neighbours(check_queue, idx, self.width, self.height);
+            add_neighbours(
+                check_queue,
+                idx,
+                self.width,
+                self.height,
+                Neighbourhood::Moore,
+                Boundary::Edges,
+            );
 
             while let Some(nb) = check_queue.pop() {
                 let nb_tile = unsafe { self.tiles.get_unchecked_mut(nb) };
@@ -126,7 +208,14 @@ impl Field {
 
                 // If it's hit the trigger, but hasn't flashed.
                 if *nb_tile >= 10 && *nb_tile & 0x80 == 0 {
-                    add_neighbours(check_queue, nb, self.width, self.height);
+                    add_neighbours(
+                        check_queue,
+                        nb,
+                        self.width,
+                        self.height,
+                        Neighbourhood::Moore,
+                        Boundary::Edges,
+                    );
                     // Mark it as flashed;
                     *nb_tile |= 0x80;
                 }
@@ -143,18 +232,240 @@ impl Field {
 
         flashes
     }
+}
+
+/// Steps a field of any size, keeping track of which octopuses have flashed
+/// separately from their energy levels.
+#[derive(Debug, Clone)]
+struct Simulator {
+    field: Field,
+    rules: Rules,
+    flashed: Vec<bool>,
+    check_queue: Vec<usize>,
+    /// The octopuses that flashed in the last step, in the order they flashed.
+    cascade: Vec<Flash>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Flash {
+    idx: usize,
+    /// 0 if the octopus flashed from the step's energy increase, otherwise one more
+    /// than the wave of the flash that set it off.
+    wave: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SyncReport {
+    /// The first step where every octopus flashed, or `None` if that never happens.
+    first_sync: Option<u64>,
+    /// The first step of the repeating sequence of states.
+    cycle_start: u64,
+    cycle_period: u64,
+}
+
+impl Simulator {
+    fn new(field: Field) -> Self {
+        Self::with_rules(field, Rules::AOC)
+    }
+
+    fn with_rules(field: Field, rules: Rules) -> Self {
+        Self {
+            rules,
+            flashed: vec![false; field.tiles.len()],
+            check_queue: Vec::new(),
+            cascade: Vec::new(),
+            field,
+        }
+    }
+
+    fn step(&mut self) -> u64 {
+        let Field {
+            tiles,
+            width,
+            height,
+        } = &mut self.field;
+        let rules = self.rules;
+
+        tiles
+            .iter_mut()
+            .for_each(|t| *t = t.saturating_add(rules.increment));
+
+        self.cascade.clear();
+        for (idx, &tile) in tiles.iter().enumerate() {
+            if tile >= rules.threshold {
+                self.flashed[idx] = true;
+                self.cascade.push(Flash { idx, wave: 0 });
+            }
+        }
+
+        // The cascade doubles as the queue of flashes that still need to spread, which
+        // keeps it in wave order.
+        let mut next_flash = 0;
+        while let Some(&flash) = self.cascade.get(next_flash) {
+            next_flash += 1;
+
+            self.check_queue.clear();
+            add_neighbours(
+                &mut self.check_queue,
+                flash.idx,
+                *width,
+                *height,
+                rules.neighbourhood,
+                rules.boundary,
+            );
+
+            for &nb in &self.check_queue {
+                tiles[nb] = tiles[nb].saturating_add(rules.spread);
+
+                // If it's hit the trigger, but hasn't flashed.
+                if tiles[nb] >= rules.threshold && !self.flashed[nb] {
+                    self.flashed[nb] = true;
+                    self.cascade.push(Flash {
+                        idx: nb,
+                        wave: flash.wave + 1,
+                    });
+                }
+            }
+        }
+
+        for &Flash { idx, .. } in &self.cascade {
+            tiles[idx] = rules.reset;
+            self.flashed[idx] = false;
+        }
+
+        self.cascade.len() as u64
+    }
+
+    fn waves(&self) -> Vec<Option<u32>> {
+        let mut waves = vec![None; self.field.tiles.len()];
+        for flash in &self.cascade {
+            waves[flash.idx] = Some(flash.wave);
+        }
+
+        waves
+    }
+
+    fn render_ansi_frame(&self, step: u64) -> String {
+        let mut output = format!("Step {}: {} flashes\n", step, self.cascade.len());
+        let waves = self.waves();
+
+        let rows = self.field.tiles.chunks_exact(self.field.width);
+        for (row, wave_row) in rows.zip(waves.chunks_exact(self.field.width)) {
+            for (&tile, &wave) in row.iter().zip(wave_row) {
+                // Writing to a String can't fail.
+                let (r, g, b) = cell_colour(tile, self.rules.threshold, wave);
+                match wave {
+                    Some(wave) => {
+                        let wave = char::from_digit(wave, 36).unwrap_or('+');
+                        write!(output, "\x1b[1;30;48;2;{};{};{}m{}", r, g, b, wave).unwrap();
+                    }
+                    None => {
+                        let energy = char::from_digit(tile as u32, 10).unwrap_or('+');
+                        write!(output, "\x1b[0;38;2;{};{};{}m{}", r, g, b, energy).unwrap();
+                    }
+                }
+            }
+            output.push_str("\x1b[0m\n");
+        }
+
+        output
+    }
 
-    #[allow(unused)]
-    fn print_table(&self) {
-        for row in self.tiles.chunks_exact(self.width) {
-            for tile in row {
-                print!("{}", tile);
+    /// Renders a binary PPM image, with each octopus drawn as a `scale` pixel square.
+    fn render_ppm_frame(&self, scale: usize) -> Vec<u8> {
+        let Field { width, height, .. } = self.field;
+        let mut output = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+        let waves = self.waves();
+
+        for y in 0..height * scale {
+            for x in 0..width * scale {
+                let idx = (y / scale) * width + x / scale;
+                let (r, g, b) =
+                    cell_colour(self.field.tiles[idx], self.rules.threshold, waves[idx]);
+                output.extend([r, g, b]);
             }
-            println!();
+        }
+
+        output
+    }
+
+    /// Runs until the field repeats a previous state. Once it does it'll keep cycling,
+    /// so if the octopuses haven't synchronised by then they never will.
+    fn find_sync(mut self) -> SyncReport {
+        let mut seen = HashMap::new();
+        let mut first_sync = None;
+        seen.insert(self.field.tiles.clone(), 0);
+
+        for step in 1.. {
+            if self.step() == self.field.tiles.len() as u64 {
+                first_sync.get_or_insert(step);
+            }
+
+            if let Some(&cycle_start) = seen.get(&self.field.tiles) {
+                return SyncReport {
+                    first_sync,
+                    cycle_start,
+                    cycle_period: step - cycle_start,
+                };
+            }
+            seen.insert(self.field.tiles.clone(), step);
+        }
+
+        unreachable!()
+    }
+}
+
+/// Flashes go from white to red as the cascade spreads, while octopuses that didn't
+/// flash get brighter as their energy builds.
+fn cell_colour(energy: u8, threshold: u8, wave: Option<u32>) -> (u8, u8, u8) {
+    const WAVE_COLOURS: [(u8, u8, u8); 6] = [
+        (255, 255, 255),
+        (255, 236, 120),
+        (255, 190, 60),
+        (255, 130, 30),
+        (230, 70, 20),
+        (170, 30, 30),
+    ];
+
+    match wave {
+        Some(wave) => WAVE_COLOURS[(wave as usize).min(WAVE_COLOURS.len() - 1)],
+        None => {
+            let energy = energy.min(threshold) as u16;
+            let level = (40 + energy * 160 / threshold.max(1) as u16) as u8;
+            (level / 2, level / 2, level)
         }
     }
 }
 
+/// Renders the first `steps` steps as a terminal replay, with each frame drawn over
+/// the last.
+fn animate_ansi(field: &Field, steps: u64) -> String {
+    let mut sim = Simulator::new(field.clone());
+    let mut output = String::from("\x1b[2J\x1b[H");
+    output += &sim.render_ansi_frame(0);
+
+    for step in 1..=steps {
+        sim.step();
+        output.push_str("\x1b[H");
+        output += &sim.render_ansi_frame(step);
+    }
+
+    output
+}
+
+#[allow(unused)]
+fn animate_ppm(field: &Field, steps: u64, scale: usize) -> Vec<Vec<u8>> {
+    let mut sim = Simulator::new(field.clone());
+    let mut frames = vec![sim.render_ppm_frame(scale)];
+
+    for _ in 0..steps {
+        sim.step();
+        frames.push(sim.render_ppm_frame(scale));
+    }
+
+    frames
+}
+
 #[cfg(test)]
 mod tests_template {
     use super::*;
@@ -219,4 +530,124 @@ mod tests_template {
 
         assert_eq!(195, flash_step);
     }
+
+    #[test]
+    fn find_sync_test() {
+        let input = aoc_lib::input(11)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let field = Field::parse(&input).unwrap();
+        let expected = SyncReport {
+            first_sync: Some(195),
+            cycle_start: 195,
+            cycle_period: 10,
+        };
+        assert_eq!(expected, Simulator::new(field).find_sync());
+
+        // The two 0s flash together, but never with the 2.
+        let field = Field::parse("002").unwrap();
+        let expected = SyncReport {
+            first_sync: None,
+            cycle_start: 0,
+            cycle_period: 9,
+        };
+        assert_eq!(expected, Simulator::new(field).find_sync());
+
+        // Energy levels which would overflow the flash bit in `Field::step`.
+        let field = Field {
+            tiles: vec![200, 0],
+            width: 2,
+            height: 1,
+        };
+        let mut sim = Simulator::new(field);
+        assert_eq!(1, sim.step());
+        assert_eq!(vec![0, 2], sim.field.tiles);
+    }
+
+    #[test]
+    fn cascade_test() {
+        let input = aoc_lib::input(11)
+            .example(Example::Part1, 2)
+            .open()
+            .unwrap();
+
+        let (start, _) = input.split_once("-----").unwrap();
+        let mut sim = Simulator::new(Field::parse(start.trim()).unwrap());
+        assert_eq!(9, sim.step());
+
+        // The 9s all flash first, then the middle is set off by them.
+        let waves = sim.waves();
+        assert_eq!(Some(0), waves[6]);
+        assert_eq!(Some(1), waves[12]);
+        assert_eq!(None, waves[0]);
+
+        let frame = sim.render_ansi_frame(1);
+        assert!(frame.starts_with("Step 1: 9 flashes\n"));
+        assert_eq!(5, frame.lines().skip(1).count());
+
+        let ppm = sim.render_ppm_frame(2);
+        let header = b"P6\n10 10\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(header.len() + 10 * 10 * 3, ppm.len());
+
+        // Top-left pixel of the middle octopus.
+        let pixel = header.len() + (2 * 2 * 10 + 2 * 2) * 3;
+        assert_eq!([255, 236, 120], ppm[pixel..pixel + 3]);
+
+        let frames = animate_ppm(&Field::parse(start.trim()).unwrap(), 2, 1);
+        assert_eq!(3, frames.len());
+    }
+
+    #[test]
+    fn rules_test() {
+        let mut queue = Vec::new();
+        let mut neighbours = |idx, neighbourhood, boundary| {
+            queue.clear();
+            add_neighbours(&mut queue, idx, 4, 4, neighbourhood, boundary);
+            queue.sort_unstable();
+            queue.clone()
+        };
+
+        use Boundary::*;
+        use Neighbourhood::*;
+        assert_eq!(vec![1, 4, 5], neighbours(0, Moore, Edges));
+        assert_eq!(vec![1, 3, 4, 5, 7, 12, 13, 15], neighbours(0, Moore, Wrap));
+        assert_eq!(vec![1, 3, 4, 12], neighbours(0, VonNeumann, Wrap));
+        assert_eq!(vec![1, 4], neighbours(0, VonNeumann, Edges));
+        assert_eq!(vec![1, 2, 4, 6, 9, 10], neighbours(5, Hex, Edges));
+        assert_eq!(vec![4, 5, 8, 10, 12, 13], neighbours(9, Hex, Edges));
+
+        // With AoC rules, the simulator has to match `Field::step`.
+        let input = aoc_lib::input(11)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+        let field = Field::parse(&input).unwrap();
+        let mut sim = Simulator::with_rules(field.clone(), Rules::AOC);
+        let flashes: u64 = (0..100).map(|_| sim.step()).sum();
+        assert_eq!(1656, flashes);
+
+        // On a torus every octopus has the same neighbours, so a uniform field stays
+        // uniform and everything flashes together.
+        let rules = Rules {
+            threshold: 5,
+            increment: 2,
+            spread: 1,
+            reset: 1,
+            neighbourhood: VonNeumann,
+            boundary: Wrap,
+        };
+        let field = Field::parse("111\n111\n111").unwrap();
+        let mut sim = Simulator::with_rules(field, rules);
+        assert_eq!(0, sim.step());
+        assert_eq!(vec![3; 9], sim.field.tiles);
+        assert_eq!(9, sim.step());
+        assert_eq!(vec![1; 9], sim.field.tiles);
+
+        let report = sim.find_sync();
+        assert_eq!(Some(2), report.first_sync);
+        assert_eq!((0, 2), (report.cycle_start, report.cycle_period));
+    }
 }
@@ -1,4 +1,9 @@
-use aoc_lib::{Bench, BenchResult, Day, NoError, ParseResult};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+};
+
+use aoc_lib::{Bench, BenchResult, Day, NoError, ParseResult, UserError};
 use lasso::{Key, Rodeo, Spur};
 
 // 8:42
@@ -10,21 +15,50 @@ pub const DAY: Day = Day {
     name: "Passage Pathing",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse)],
+    other: &[
+        ("Parse", run_parse),
+        ("Part 2 Paths", run_part2_paths),
+        ("Part 1 Memoised", run_part1_memoised),
+        ("Part 2 Memoised", run_part2_memoised),
+        ("Validate", run_validate),
+    ],
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
-    let cave_system = CaveSystem::parse(input);
+    let cave_system = CaveSystem::parse(input).map_err(UserError)?;
     b.bench(|| Ok::<_, NoError>(part1(&cave_system)))
 }
 
 fn run_part2(input: &str, b: Bench) -> BenchResult {
-    let cave_system = CaveSystem::parse(input);
+    let cave_system = CaveSystem::parse(input).map_err(UserError)?;
     b.bench(|| Ok::<_, NoError>(part2(&cave_system)))
 }
 
+fn run_part2_paths(input: &str, b: Bench) -> BenchResult {
+    let cave_system = CaveSystem::parse(input).map_err(UserError)?;
+    b.bench(|| {
+        let policy = VisitPolicy::part2(&cave_system);
+        Ok::<_, NoError>(cave_system.paths(policy, PathOrder::Input).count())
+    })
+}
+
+fn run_part1_memoised(input: &str, b: Bench) -> BenchResult {
+    let cave_system = CaveSystem::parse(input).map_err(UserError)?;
+    b.bench(|| Ok::<_, NoError>(count_memoised(&cave_system, false)))
+}
+
+fn run_part2_memoised(input: &str, b: Bench) -> BenchResult {
+    let cave_system = CaveSystem::parse(input).map_err(UserError)?;
+    b.bench(|| Ok::<_, NoError>(count_memoised(&cave_system, true)))
+}
+
+fn run_validate(input: &str, b: Bench) -> BenchResult {
+    let cave_system = CaveSystem::parse(input).map_err(UserError)?;
+    b.bench_alt(|| render_warnings(&cave_system))
+}
+
 fn run_parse(input: &str, b: Bench) -> BenchResult {
-    b.bench(|| Ok::<_, NoError>(ParseResult(CaveSystem::parse(input))))
+    b.bench(|| CaveSystem::parse(input).map(ParseResult))
 }
 
 #[derive(Default)]
@@ -40,6 +74,102 @@ struct CaveSystem {
     interner: Rodeo,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CaveError {
+    BadLine {
+        line: usize,
+        text: String,
+    },
+    /// Cave names have to be either all uppercase or all lowercase.
+    BadName {
+        line: usize,
+        name: String,
+    },
+    /// A tunnel which leads back to the cave it started from.
+    SelfLoop {
+        line: usize,
+        cave: String,
+    },
+    DuplicateTunnel {
+        line: usize,
+        first_line: usize,
+    },
+    MissingEntry,
+    MissingExit,
+    /// Two big caves joined directly, which would allow infinitely many paths.
+    AdjacentBigCaves {
+        line: usize,
+        a: String,
+        b: String,
+    },
+}
+
+impl Display for CaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaveError::BadLine { line, text } => {
+                write!(f, "line {}: invalid tunnel `{}`", line, text)
+            }
+            CaveError::BadName { line, name } => {
+                write!(f, "line {}: invalid cave name `{}`", line, name)
+            }
+            CaveError::SelfLoop { line, cave } => {
+                write!(
+                    f,
+                    "line {}: tunnel from `{}` leads back to itself",
+                    line, cave
+                )
+            }
+            CaveError::DuplicateTunnel { line, first_line } => {
+                write!(
+                    f,
+                    "line {}: tunnel already given on line {}",
+                    line, first_line
+                )
+            }
+            CaveError::MissingEntry => f.write_str("no start cave found"),
+            CaveError::MissingExit => f.write_str("no end cave found"),
+            CaveError::AdjacentBigCaves { line, a, b } => write!(
+                f,
+                "line {}: big caves `{}` and `{}` are connected, giving infinitely many paths",
+                line, a, b
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CaveError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CaveWarning {
+    /// The cave can't be reached from the start.
+    Unreachable(String),
+    /// The end can't be reached from the cave.
+    DeadEnd(String),
+}
+
+impl Display for CaveWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaveWarning::Unreachable(cave) => write!(f, "`{}` can't be reached from start", cave),
+            CaveWarning::DeadEnd(cave) => write!(f, "`{}` can't reach end", cave),
+        }
+    }
+}
+
+fn is_big_cave(name: &str, line: usize) -> Result<bool, CaveError> {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase()) {
+        Ok(true)
+    } else if !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase()) {
+        Ok(false)
+    } else {
+        Err(CaveError::BadName {
+            line,
+            name: name.to_owned(),
+        })
+    }
+}
+
 impl CaveSystem {
     fn get_cave(caves: &mut Vec<Cave>, id: Spur) -> &mut Cave {
         let idx = id.into_usize();
@@ -49,127 +179,475 @@ impl CaveSystem {
         &mut caves[idx]
     }
 
-    fn parse(input: &str) -> CaveSystem {
-        let mut entry = None;
-        let mut exit = None;
+    fn parse(input: &str) -> Result<CaveSystem, CaveError> {
         let mut caves = Vec::new();
-        let mut interner = Rodeo::new();
+        let mut interner: Rodeo = Rodeo::new();
+        let mut tunnel_lines: HashMap<(Spur, Spur), usize> = HashMap::new();
+
+        for (line, text) in input.trim().lines().enumerate() {
+            let line = line + 1;
+            let (start, end) = text.split_once('-').ok_or_else(|| CaveError::BadLine {
+                line,
+                text: text.to_owned(),
+            })?;
+
+            let start_is_big = is_big_cave(start, line)?;
+            let end_is_big = is_big_cave(end, line)?;
+            if start == end {
+                return Err(CaveError::SelfLoop {
+                    line,
+                    cave: start.to_owned(),
+                });
+            }
+            if start_is_big && end_is_big {
+                return Err(CaveError::AdjacentBigCaves {
+                    line,
+                    a: start.to_owned(),
+                    b: end.to_owned(),
+                });
+            }
 
-        for line in input.trim().lines() {
-            let (start, end) = line.split_once('-').expect("invalid line");
             let start_spur = interner.get_or_intern(start);
             let end_spur = interner.get_or_intern(end);
 
-            if start == "start" {
-                entry = Some(start_spur);
-            } else if end == "start" {
-                entry = Some(end_spur);
-            } else if start == "end" {
-                exit = Some(start_spur);
-            } else if end == "end" {
-                exit = Some(end_spur);
+            let key = (start_spur.min(end_spur), start_spur.max(end_spur));
+            if let Some(&first_line) = tunnel_lines.get(&key) {
+                return Err(CaveError::DuplicateTunnel { line, first_line });
             }
+            tunnel_lines.insert(key, line);
 
             let start_cave = CaveSystem::get_cave(&mut caves, start_spur);
-            start_cave.is_big = start.chars().all(|c| c.is_uppercase());
+            start_cave.is_big = start_is_big;
             start_cave.leads_to.push(end_spur);
 
             let end_cave = CaveSystem::get_cave(&mut caves, end_spur);
-            end_cave.is_big = end.chars().all(|c| c.is_uppercase());
+            end_cave.is_big = end_is_big;
             end_cave.leads_to.push(start_spur);
         }
 
-        CaveSystem {
-            entry: entry.expect("no start found"),
-            exit: exit.expect("no end found"),
+        Ok(CaveSystem {
+            entry: interner.get("start").ok_or(CaveError::MissingEntry)?,
+            exit: interner.get("end").ok_or(CaveError::MissingExit)?,
             caves,
             interner,
+        })
+    }
+
+    /// Finds every cave reachable from `from` without passing through `barrier`.
+    fn reachable(&self, from: Spur, barrier: Spur) -> Vec<bool> {
+        let mut seen = vec![false; self.caves.len()];
+        seen[from.into_usize()] = true;
+        let mut queue = vec![from];
+
+        while let Some(cave) = queue.pop() {
+            if cave == barrier {
+                continue;
+            }
+            for &next in &self.caves[cave.into_usize()].leads_to {
+                if !std::mem::replace(&mut seen[next.into_usize()], true) {
+                    queue.push(next);
+                }
+            }
         }
+
+        seen
     }
 
-    fn traverse_paths_part1(&self, root: Spur, visited: &mut [bool]) -> usize {
-        if root == self.exit {
-            return 1;
+    /// Finds caves which can't be part of any path from start to end.
+    fn warnings(&self) -> Vec<CaveWarning> {
+        let from_entry = self.reachable(self.entry, self.exit);
+        let to_exit = self.reachable(self.exit, self.entry);
+
+        let mut warnings = Vec::new();
+        for (cave, name) in self.interner.iter() {
+            let idx = cave.into_usize();
+            if !from_entry[idx] {
+                warnings.push(CaveWarning::Unreachable(name.to_owned()));
+            } else if !to_exit[idx] {
+                warnings.push(CaveWarning::DeadEnd(name.to_owned()));
+            }
+        }
+
+        warnings
+    }
+}
+
+fn render_warnings(cave_system: &CaveSystem) -> Result<String, NoError> {
+    let mut output = String::new();
+    for warning in cave_system.warnings() {
+        writeln!(output, "warning: {}", warning).unwrap();
+    }
+
+    Ok(output)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PathOrder {
+    /// Tunnels are taken in the order they appear in the input.
+    Input,
+    /// Paths are sorted by their cave names.
+    Lexicographic,
+}
+
+/// Which caves a path can visit, and how often. The path can never return to the
+/// start, and ends as soon as it reaches the end.
+#[derive(Debug, Clone)]
+struct VisitPolicy {
+    /// How many times each cave can be visited, or `None` if there's no limit.
+    max_visits: Vec<Option<u16>>,
+    /// How many visits beyond a cave's maximum can be made over the whole path...
+    extra_visits: u16,
+    /// ...and how many of those can be spent on a single cave.
+    extra_per_cave: u16,
+    forbidden: Vec<bool>,
+    /// Caves the path has to pass through.
+    waypoints: Vec<Spur>,
+}
+
+impl VisitPolicy {
+    /// Small caves can be visited once, and big caves any number of times.
+    fn new(cave_system: &CaveSystem) -> Self {
+        let max_visits = cave_system
+            .caves
+            .iter()
+            .map(|cave| (!cave.is_big).then_some(1))
+            .collect();
+
+        Self {
+            max_visits,
+            extra_visits: 0,
+            extra_per_cave: 0,
+            forbidden: vec![false; cave_system.caves.len()],
+            waypoints: Vec::new(),
+        }
+    }
+
+    fn part1(cave_system: &CaveSystem) -> Self {
+        Self::new(cave_system)
+    }
+
+    /// A single small cave can be visited twice.
+    fn part2(cave_system: &CaveSystem) -> Self {
+        Self {
+            extra_visits: 1,
+            extra_per_cave: 1,
+            ..Self::new(cave_system)
         }
-        let cave = &self.caves[root.into_usize()];
+    }
 
-        if !cave.is_big {
-            if visited[root.into_usize()] {
-                return 0;
+    #[allow(unused)]
+    fn set_max_visits(&mut self, cave: Spur, max_visits: Option<u16>) {
+        self.max_visits[cave.into_usize()] = max_visits;
+    }
+
+    #[allow(unused)]
+    fn forbid(&mut self, cave: Spur) {
+        self.forbidden[cave.into_usize()] = true;
+    }
+
+    fn require(&mut self, cave: Spur) {
+        self.waypoints.push(cave);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Step {
+    cave: Spur,
+    next_tunnel: usize,
+    used_extra: bool,
+}
+
+/// Walks the cave system depth-first, following whatever rules the policy sets.
+struct Traversal<'a> {
+    cave_system: &'a CaveSystem,
+    policy: VisitPolicy,
+    tunnels: Vec<Vec<Spur>>,
+    stack: Vec<Step>,
+    visits: Vec<u16>,
+    extra_used: u16,
+}
+
+impl<'a> Traversal<'a> {
+    fn new(cave_system: &'a CaveSystem, policy: VisitPolicy, order: PathOrder) -> Self {
+        let mut tunnels: Vec<_> = cave_system
+            .caves
+            .iter()
+            .map(|c| c.leads_to.clone())
+            .collect();
+
+        if order == PathOrder::Lexicographic {
+            for leads_to in &mut tunnels {
+                leads_to.sort_by_key(|&c| cave_system.interner.resolve(&c));
             }
-            visited[root.into_usize()] = true;
         }
 
-        let mut num_paths = 0;
-        for &next_cave in &cave.leads_to {
-            num_paths += self.traverse_paths_part1(next_cave, visited);
+        let mut traversal = Traversal {
+            cave_system,
+            policy,
+            tunnels,
+            stack: Vec::new(),
+            visits: vec![0; cave_system.caves.len()],
+            extra_used: 0,
+        };
+        traversal.enter(cave_system.entry, false);
+
+        traversal
+    }
+
+    /// Returns whether the cave can be entered, and if so whether it needs an extra visit.
+    fn can_enter(&self, cave: Spur) -> Option<bool> {
+        let idx = cave.into_usize();
+        if cave == self.cave_system.entry || self.policy.forbidden[idx] {
+            return None;
         }
 
-        if !cave.is_big {
-            visited[root.into_usize()] = false;
+        let visits = self.visits[idx];
+        match self.policy.max_visits[idx] {
+            None => Some(false),
+            Some(max) if visits < max => Some(false),
+            Some(max)
+                if visits < max + self.policy.extra_per_cave
+                    && self.extra_used < self.policy.extra_visits =>
+            {
+                Some(true)
+            }
+            Some(_) => None,
         }
+    }
 
-        num_paths
+    fn enter(&mut self, cave: Spur, used_extra: bool) {
+        self.visits[cave.into_usize()] += 1;
+        self.extra_used += used_extra as u16;
+        self.stack.push(Step {
+            cave,
+            next_tunnel: 0,
+            used_extra,
+        });
     }
 
-    fn traverse_paths_part2(
-        &self,
-        root: Spur,
-        mut visited_twice: bool,
-        visited: &mut [u16],
-    ) -> usize {
-        if root == self.exit {
-            return 1;
+    fn leave(&mut self) {
+        if let Some(step) = self.stack.pop() {
+            self.visits[step.cave.into_usize()] -= 1;
+            self.extra_used -= step.used_extra as u16;
         }
-        let cave = &self.caves[root.into_usize()];
-        let visit_count = visited[root.into_usize()];
-        if root == self.entry || (!cave.is_big && visited_twice && visit_count >= 1) {
-            return 0;
+    }
+
+    /// Moves on to the next path that reaches the end, returning false once there are
+    /// none left.
+    fn advance(&mut self) -> bool {
+        // We're still sat on the end of the last path.
+        if matches!(self.stack.last(), Some(step) if step.cave == self.cave_system.exit) {
+            self.leave();
         }
 
-        if !cave.is_big {
-            visited[root.into_usize()] += 1;
-            visited_twice |= visited[root.into_usize()] >= 2;
+        while let Some(step) = self.stack.last_mut() {
+            let cave = step.cave;
+
+            if cave == self.cave_system.exit {
+                let visits = &self.visits;
+                if (self.policy.waypoints.iter()).all(|w| visits[w.into_usize()] > 0) {
+                    return true;
+                }
+                self.leave();
+                continue;
+            }
+
+            match self.tunnels[cave.into_usize()].get(step.next_tunnel) {
+                Some(&next_cave) => {
+                    step.next_tunnel += 1;
+                    if let Some(used_extra) = self.can_enter(next_cave) {
+                        self.enter(next_cave, used_extra);
+                    }
+                }
+                None => self.leave(),
+            }
         }
+
+        false
+    }
+
+    fn count(mut self) -> usize {
         let mut num_paths = 0;
-        for &next_cave in &cave.leads_to {
-            num_paths += self.traverse_paths_part2(next_cave, visited_twice, visited);
+        while self.advance() {
+            num_paths += 1;
         }
 
-        if !cave.is_big {
-            visited[root.into_usize()] -= 1;
+        num_paths
+    }
+}
+
+/// Yields each path from start to end as it's found.
+struct Paths<'a>(Traversal<'a>);
+
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.0.advance() {
+            return None;
         }
 
-        num_paths
+        let interner = &self.0.cave_system.interner;
+        let path = self.0.stack.iter().map(|s| interner.resolve(&s.cave));
+        Some(path.collect())
     }
 }
 
-fn part1(cave_system: &CaveSystem) -> usize {
-    let entry_cave = &cave_system.caves[cave_system.entry.into_usize()];
+#[derive(Debug, Clone, Copy)]
+struct PathQuery<'a> {
+    policy: &'a VisitPolicy,
+    order: PathOrder,
+    /// Only find paths which pass through all of these caves.
+    through: &'a [&'a str],
+    limit: Option<usize>,
+}
 
-    let mut visited = vec![false; cave_system.interner.len()];
-    visited[cave_system.entry.into_usize()] = true;
+impl CaveSystem {
+    fn paths(&self, policy: VisitPolicy, order: PathOrder) -> Paths<'_> {
+        Paths(Traversal::new(self, policy, order))
+    }
 
-    let mut num_paths = 0;
-    for &next_cave in &entry_cave.leads_to {
-        num_paths += cave_system.traverse_paths_part1(next_cave, &mut visited);
+    fn count_paths(&self, policy: VisitPolicy) -> usize {
+        Traversal::new(self, policy, PathOrder::Input).count()
     }
 
-    num_paths
+    #[allow(unused)]
+    fn find_paths(&self, query: &PathQuery) -> Vec<Vec<&str>> {
+        let mut policy = query.policy.clone();
+        for cave in query.through {
+            match self.interner.get(cave) {
+                Some(cave) => policy.require(cave),
+                None => return Vec::new(),
+            }
+        }
+
+        self.paths(policy, query.order)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
 }
 
-fn part2(cave_system: &CaveSystem) -> usize {
-    let entry_cave = &cave_system.caves[cave_system.entry.into_usize()];
+/// Counts paths with a search memoised on the current cave, which small caves have
+/// been visited, and whether one has been visited twice.
+///
+/// Big caves are contracted away: a tunnel through a big cave becomes a tunnel between
+/// the small caves either side of it, weighted by how many ways there are through.
+struct PathCounter {
+    entry: usize,
+    exit: usize,
+    /// The small caves reachable from each small cave, and the number of ways to get there.
+    tunnels: Vec<Vec<(usize, u64)>>,
+    memo: HashMap<(usize, u64, bool), u64>,
+}
 
-    let mut visited = vec![0; cave_system.interner.len() + 1];
-    visited[cave_system.entry.into_usize()] = 1;
+impl PathCounter {
+    /// The visited set is a `u64` bitmask, so this gives `None` for cave systems with
+    /// more than 64 small caves.
+    fn new(cave_system: &CaveSystem) -> Option<Self> {
+        let mut small_idx = vec![None; cave_system.caves.len()];
+        let mut num_small = 0;
+        for (idx, cave) in cave_system.caves.iter().enumerate() {
+            if !cave.is_big {
+                small_idx[idx] = Some(num_small);
+                num_small += 1;
+            }
+        }
+        if num_small > 64 {
+            return None;
+        }
 
-    let mut num_paths = 0;
-    for &next_cave in &entry_cave.leads_to {
-        num_paths += cave_system.traverse_paths_part2(next_cave, false, &mut visited);
+        let mut weights = vec![vec![0; num_small]; num_small];
+        for (idx, cave) in cave_system.caves.iter().enumerate() {
+            let from = match small_idx[idx] {
+                Some(from) => from,
+                None => continue,
+            };
+
+            for &next in &cave.leads_to {
+                let next_cave = &cave_system.caves[next.into_usize()];
+                match small_idx[next.into_usize()] {
+                    Some(to) => weights[from][to] += 1,
+                    None => {
+                        for &beyond in &next_cave.leads_to {
+                            // Big caves next to each other would give infinitely many
+                            // paths, so we don't go any further.
+                            if let Some(to) = small_idx[beyond.into_usize()] {
+                                weights[from][to] += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let tunnels = weights
+            .into_iter()
+            .map(|row| {
+                let tunnels = row.into_iter().enumerate();
+                tunnels.filter(|&(_, ways)| ways > 0).collect()
+            })
+            .collect();
+
+        Some(Self {
+            entry: small_idx[cave_system.entry.into_usize()].unwrap(),
+            exit: small_idx[cave_system.exit.into_usize()].unwrap(),
+            tunnels,
+            memo: HashMap::new(),
+        })
+    }
+
+    fn count_from(&mut self, cave: usize, visited: u64, twice_used: bool) -> u64 {
+        if cave == self.exit {
+            return 1;
+        }
+        if let Some(&count) = self.memo.get(&(cave, visited, twice_used)) {
+            return count;
+        }
+
+        let mut count = 0;
+        for idx in 0..self.tunnels[cave].len() {
+            let (next, ways) = self.tunnels[cave][idx];
+            if next == self.entry {
+                continue;
+            }
+
+            let next_count = if visited & (1 << next) == 0 {
+                self.count_from(next, visited | (1 << next), twice_used)
+            } else if !twice_used {
+                self.count_from(next, visited, true)
+            } else {
+                0
+            };
+            count += ways * next_count;
+        }
+
+        self.memo.insert((cave, visited, twice_used), count);
+        count
     }
 
-    num_paths
+    /// If `allow_twice` is set, a single small cave can be visited twice.
+    fn count(&mut self, allow_twice: bool) -> u64 {
+        self.memo.clear();
+        self.count_from(self.entry, 1 << self.entry, !allow_twice)
+    }
+}
+
+/// Counts with `PathCounter` where it can, falling back to walking every path when
+/// there are too many small caves for it.
+fn count_memoised(cave_system: &CaveSystem, allow_twice: bool) -> u64 {
+    match PathCounter::new(cave_system) {
+        Some(mut counter) => counter.count(allow_twice),
+        None if allow_twice => part2(cave_system) as u64,
+        None => part1(cave_system) as u64,
+    }
+}
+
+fn part1(cave_system: &CaveSystem) -> usize {
+    cave_system.count_paths(VisitPolicy::part1(cave_system))
+}
+
+fn part2(cave_system: &CaveSystem) -> usize {
+    cave_system.count_paths(VisitPolicy::part2(cave_system))
 }
 
 #[cfg(test)]
@@ -184,7 +662,7 @@ mod tests_template {
             .open()
             .unwrap();
 
-        let cave_system = CaveSystem::parse(&input);
+        let cave_system = CaveSystem::parse(&input).unwrap();
         let num_paths = part1(&cave_system);
         assert_eq!(10, num_paths);
     }
@@ -196,7 +674,7 @@ mod tests_template {
             .open()
             .unwrap();
 
-        let cave_system = CaveSystem::parse(&input);
+        let cave_system = CaveSystem::parse(&input).unwrap();
         let num_paths = part1(&cave_system);
         assert_eq!(19, num_paths);
     }
@@ -208,8 +686,202 @@ mod tests_template {
             .open()
             .unwrap();
 
-        let cave_system = CaveSystem::parse(&input);
+        let cave_system = CaveSystem::parse(&input).unwrap();
         let num_paths = part2(&cave_system);
         assert_eq!(36, num_paths);
     }
+
+    #[test]
+    fn paths_test() {
+        let input = aoc_lib::input(12)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let cave_system = CaveSystem::parse(&input).unwrap();
+        let part1_policy = VisitPolicy::part1(&cave_system);
+        let part2_policy = VisitPolicy::part2(&cave_system);
+
+        let paths = cave_system.paths(part1_policy.clone(), PathOrder::Input);
+        assert_eq!(10, paths.count());
+        let paths = cave_system.paths(part2_policy.clone(), PathOrder::Input);
+        assert_eq!(36, paths.count());
+
+        let query = PathQuery {
+            policy: &part1_policy,
+            order: PathOrder::Lexicographic,
+            through: &[],
+            limit: None,
+        };
+        let paths = cave_system.find_paths(&query);
+        let mut sorted = paths.clone();
+        sorted.sort();
+        assert_eq!(sorted, paths);
+        assert_eq!(vec!["start", "A", "b", "A", "c", "A", "end"], paths[0]);
+
+        let query = PathQuery {
+            through: &["c", "d"],
+            ..query
+        };
+        assert!(cave_system.find_paths(&query).is_empty());
+
+        let query = PathQuery {
+            policy: &part2_policy,
+            through: &["d"],
+            limit: Some(2),
+            ..query
+        };
+        let expected = vec![
+            vec!["start", "A", "b", "d", "b", "A", "c", "A", "end"],
+            vec!["start", "A", "b", "d", "b", "A", "end"],
+        ];
+        assert_eq!(expected, cave_system.find_paths(&query));
+    }
+
+    #[test]
+    fn path_counter_test() {
+        let input = aoc_lib::input(12)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let cave_system = CaveSystem::parse(&input).unwrap();
+        let mut counter = PathCounter::new(&cave_system).unwrap();
+        assert_eq!(10, counter.count(false));
+        assert_eq!(36, counter.count(true));
+
+        let input = aoc_lib::input(12)
+            .example(Example::Part1, 2)
+            .open()
+            .unwrap();
+
+        let cave_system = CaveSystem::parse(&input).unwrap();
+        let mut counter = PathCounter::new(&cave_system).unwrap();
+        assert_eq!(19, counter.count(false));
+        assert_eq!(103, counter.count(true));
+
+        // Too many small caves for the bitmask, so it has to walk them instead.
+        let input: String = (b'a'..=b'z')
+            .flat_map(|a| (b'a'..=b'c').map(move |b| format!("{}{}", a as char, b as char)))
+            .map(|cave| format!("start-{}\n{}-end\n", cave, cave))
+            .collect();
+        let cave_system = CaveSystem::parse(&input).unwrap();
+        assert!(PathCounter::new(&cave_system).is_none());
+        assert_eq!(78, count_memoised(&cave_system, false));
+        assert_eq!(78, count_memoised(&cave_system, true));
+    }
+
+    #[test]
+    fn visit_policy_test() {
+        let input = aoc_lib::input(12)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let cave_system = CaveSystem::parse(&input).unwrap();
+        let cave = |name| cave_system.interner.get(name).unwrap();
+
+        // Any two small caves can be visited twice.
+        let policy = VisitPolicy {
+            extra_visits: 2,
+            extra_per_cave: 1,
+            ..VisitPolicy::new(&cave_system)
+        };
+        let mut counter = PathCounter::new(&cave_system).unwrap();
+        assert!(cave_system.count_paths(policy.clone()) > counter.count(true) as usize);
+        for path in cave_system.paths(policy, PathOrder::Input) {
+            let mut small: Vec<_> = path
+                .iter()
+                .filter(|c| c.chars().all(char::is_lowercase))
+                .collect();
+            small.sort_unstable();
+            let len = small.len();
+            small.dedup();
+            assert!(len - small.len() <= 2);
+        }
+
+        let mut policy = VisitPolicy::part1(&cave_system);
+        policy.forbid(cave("A"));
+        let expected = vec![vec!["start", "b", "end"]];
+        assert_eq!(
+            expected,
+            cave_system
+                .paths(policy, PathOrder::Input)
+                .collect::<Vec<_>>()
+        );
+
+        // A can be visited at most twice, and c has to be visited.
+        let mut policy = VisitPolicy::part1(&cave_system);
+        policy.set_max_visits(cave("A"), Some(2));
+        policy.require(cave("c"));
+        let paths: Vec<_> = cave_system
+            .paths(policy, PathOrder::Lexicographic)
+            .collect();
+        let expected = vec![
+            vec!["start", "A", "c", "A", "b", "end"],
+            vec!["start", "A", "c", "A", "end"],
+            vec!["start", "b", "A", "c", "A", "end"],
+        ];
+        assert_eq!(expected, paths);
+    }
+
+    #[test]
+    fn validation_test() {
+        let err = |input| CaveSystem::parse(input).err();
+
+        let bad_line = CaveError::BadLine {
+            line: 2,
+            text: "A".to_owned(),
+        };
+        assert_eq!(Some(bad_line), err("start-A\nA\nA-end"));
+
+        let bad_name = CaveError::BadName {
+            line: 1,
+            name: "Ab".to_owned(),
+        };
+        assert_eq!(Some(bad_name), err("start-Ab\nAb-end"));
+
+        let self_loop = CaveError::SelfLoop {
+            line: 1,
+            cave: "start".to_owned(),
+        };
+        assert_eq!(Some(self_loop), err("start-start\nstart-end"));
+
+        let duplicate = CaveError::DuplicateTunnel {
+            line: 3,
+            first_line: 1,
+        };
+        assert_eq!(Some(duplicate), err("start-A\nA-end\nA-start"));
+
+        assert_eq!(Some(CaveError::MissingEntry), err("a-end"));
+        assert_eq!(Some(CaveError::MissingExit), err("start-a"));
+
+        let adjacent = CaveError::AdjacentBigCaves {
+            line: 2,
+            a: "A".to_owned(),
+            b: "B".to_owned(),
+        };
+        assert_eq!(Some(adjacent), err("start-A\nA-B\nB-end"));
+    }
+
+    #[test]
+    fn warnings_test() {
+        let input = aoc_lib::input(12)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+        let cave_system = CaveSystem::parse(&input).unwrap();
+        assert_eq!(Vec::<CaveWarning>::new(), cave_system.warnings());
+
+        // q can only lead back to the start, y and z are cut off, and w hangs off the end.
+        let input = "start-A\nA-end\nstart-q\ny-z\nend-w";
+        let cave_system = CaveSystem::parse(input).unwrap();
+        let expected = vec![
+            CaveWarning::DeadEnd("q".to_owned()),
+            CaveWarning::Unreachable("y".to_owned()),
+            CaveWarning::Unreachable("z".to_owned()),
+            CaveWarning::Unreachable("w".to_owned()),
+        ];
+        assert_eq!(expected, cave_system.warnings());
+    }
 }
@@ -1,6 +1,7 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt::Display};
 
 use aoc_lib::{Bench, BenchResult, Day, NoError, ParseResult};
+use color_eyre::Report;
 
 //11:32
 //12:50 (Part 2)
@@ -10,36 +11,70 @@ pub const DAY: Day = Day {
     name: "Transparent Origami",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse)],
+    other: &[
+        ("Parse", run_parse),
+        ("Part 1 Dense", run_part1_dense),
+        ("Part 2 Render", run_part2_render),
+        ("Replay", run_replay),
+    ],
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
-    let (paper, instrs) = PaperInstructionsPart1::parse(input);
+    let (points, instrs) = parse(input);
     b.bench(|| {
-        Ok::<_, NoError>({
-            let mut paper = paper.clone();
-            paper.make_folds(&instrs[..1]);
+        let mut paper = Paper::new(&points, Backend::Sparse);
+        paper.fold(instrs[0])?;
 
-            paper.count_dots()
-        })
+        Ok::<_, FoldError>(paper.count_dots())
+    })
+}
+
+fn run_part1_dense(input: &str, b: Bench) -> BenchResult {
+    let (points, instrs) = parse(input);
+    b.bench(|| {
+        let mut paper = Paper::new(&points, Backend::Dense);
+        paper.fold(instrs[0])?;
+
+        Ok::<_, FoldError>(paper.count_dots())
     })
 }
 
 fn run_part2(input: &str, b: Bench) -> BenchResult {
-    let (paper, instrs) = PaperInstructionsPart2::parse(input);
-    b.bench_alt(|| {
-        Ok::<_, NoError>({
-            let mut paper = paper.clone();
+    let (points, instrs) = parse(input);
+    b.bench(|| {
+        let mut paper = Paper::new(&points, Backend::Dense);
+        paper.make_folds(&instrs)?;
 
-            paper.make_folds(&instrs);
+        read_letters(&paper.to_dense(), paper.width, paper.height).map_err(Report::from)
+    })
+}
 
-            paper.print_paper()
-        })
+fn run_part2_render(input: &str, b: Bench) -> BenchResult {
+    let (points, instrs) = parse(input);
+    b.bench_alt(|| {
+        let mut paper = Paper::new(&points, Backend::Dense);
+        paper.make_folds(&instrs)?;
+
+        Ok::<_, FoldError>(paper.print_paper())
     })
 }
 
+fn run_replay(input: &str, b: Bench) -> BenchResult {
+    let (points, instrs) = parse(input);
+    let paper = Paper::new(&points, Backend::Dense);
+    b.bench_alt(|| render_replay(&paper, &instrs))
+}
+
 fn run_parse(input: &str, b: Bench) -> BenchResult {
-    b.bench(|| Ok::<_, NoError>(ParseResult(PaperInstructionsPart1::parse(input))))
+    b.bench(|| Ok::<_, NoError>(ParseResult(parse(input))))
+}
+
+fn parse(input: &str) -> (Vec<Point>, Vec<Instruction>) {
+    let (point_str, instr_str) = input.split_once("\n\n").expect("Segment break not found");
+    let points = point_str.trim().lines().map(Point::parse).collect();
+    let instrs = instr_str.trim().lines().map(Instruction::parse).collect();
+
+    (points, instrs)
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -57,23 +92,9 @@ impl Point {
             y: y.trim().parse().unwrap(),
         }
     }
-
-    fn reflect(self, instruction: Instruction) -> Self {
-        match instruction {
-            Instruction::Y(y) if self.y > y => {
-                let y = 2 * y - self.y;
-                Self { y, ..self }
-            }
-            Instruction::X(x) if self.x > x => {
-                let x = 2 * x - self.x;
-                Self { x, ..self }
-            }
-            _ => self,
-        }
-    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Instruction {
     Y(usize),
     X(usize),
@@ -95,112 +116,220 @@ impl Instruction {
     }
 }
 
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Y(y) => write!(f, "fold along y={}", y),
+            Instruction::X(x) => write!(f, "fold along x={}", x),
+        }
+    }
+}
+
+/// Moves a coordinate onto the folded sheet, which is `folded_len` long. The fold
+/// line itself doesn't survive, and whichever side is longer sets the new size, so
+/// the origin moves if the far side overhangs the near side.
+fn fold_coord(coord: usize, line: usize, folded_len: usize) -> usize {
+    folded_len - line.abs_diff(coord)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FoldError {
+    DotsOnLine {
+        instruction: Instruction,
+        dots: usize,
+    },
+    OutsidePaper {
+        instruction: Instruction,
+        size: usize,
+    },
+    /// The fold line is beyond the edge of the folded sheet, so it can't have been made.
+    CannotUnfold {
+        instruction: Instruction,
+        size: usize,
+    },
+}
+
+impl Display for FoldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FoldError::DotsOnLine { instruction, dots } => {
+                write!(f, "{}: {} dots lie on the fold line", instruction, dots)
+            }
+            FoldError::OutsidePaper { instruction, size } => {
+                write!(f, "{}: paper is only {} dots across", instruction, size)
+            }
+            FoldError::CannotUnfold { instruction, size } => write!(
+                f,
+                "{}: folded paper is only {} dots across",
+                instruction, size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FoldError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Sparse,
+    Dense,
+}
+
 #[derive(Debug, Clone)]
-struct PaperInstructionsPart1 {
-    points: Vec<Point>,
-    folded: HashSet<Point>,
-    folded_width: usize,
-    folded_height: usize,
+enum Dots {
+    Sparse(HashSet<Point>),
+    /// Row-major, `width` dots across.
+    Dense(Vec<bool>),
 }
 
-impl PaperInstructionsPart1 {
-    fn parse(input: &str) -> (Self, Vec<Instruction>) {
-        let (point_str, instr_str) = input.split_once("\n\n").expect("Segment break not found");
+#[derive(Debug, Clone)]
+struct Paper {
+    dots: Dots,
+    width: usize,
+    height: usize,
+}
 
-        let points: Vec<_> = point_str.trim().lines().map(Point::parse).collect();
+impl Paper {
+    /// Makes a sheet just big enough to hold all the points.
+    fn new(points: &[Point], backend: Backend) -> Self {
         let (width, height) = points
             .iter()
             .fold((0, 0), |(x, y), p| (x.max(p.x + 1), y.max(p.y + 1)));
 
-        let instrs = instr_str.trim().lines().map(Instruction::parse).collect();
-
-        (
-            Self {
-                points,
-                folded: HashSet::new(),
-                folded_height: height,
-                folded_width: width,
-            },
-            instrs,
-        )
+        Self::with_size(points, width, height, backend)
     }
 
-    #[allow(unused)]
-    fn print_paper(&self) -> String {
-        let mut output = String::new();
-
-        for y in 0..self.folded_height {
-            for x in 0..self.folded_width {
-                if self.folded.contains(&Point { x, y }) {
-                    output.push('#');
-                } else {
-                    output.push(' ');
+    fn with_size(points: &[Point], width: usize, height: usize, backend: Backend) -> Self {
+        let dots = match backend {
+            Backend::Sparse => Dots::Sparse(points.iter().copied().collect()),
+            Backend::Dense => {
+                let mut dots = vec![false; width * height];
+                for p in points {
+                    dots[p.y * width + p.x] = true;
                 }
+                Dots::Dense(dots)
             }
-            output.push('\n');
+        };
+
+        Self {
+            dots,
+            width,
+            height,
         }
+    }
 
-        output
+    fn contains(&self, point: Point) -> bool {
+        match &self.dots {
+            Dots::Sparse(dots) => dots.contains(&point),
+            Dots::Dense(dots) => {
+                point.x < self.width
+                    && point.y < self.height
+                    && dots[point.y * self.width + point.x]
+            }
+        }
+    }
+
+    fn points(&self) -> Vec<Point> {
+        match &self.dots {
+            Dots::Sparse(dots) => dots.iter().copied().collect(),
+            Dots::Dense(dots) => (dots.iter().enumerate())
+                .filter(|(_, &dot)| dot)
+                .map(|(idx, _)| Point {
+                    x: idx % self.width,
+                    y: idx / self.width,
+                })
+                .collect(),
+        }
     }
 
-    fn make_folds(&mut self, instructions: &[Instruction]) {
-        for point in &self.points {
-            let new_point = instructions
-                .iter()
-                .fold(*point, |p, instr| p.reflect(*instr));
-            self.folded.insert(new_point);
+    fn fold(&mut self, instruction: Instruction) -> Result<(), FoldError> {
+        let (line, size) = match instruction {
+            Instruction::Y(y) => (y, self.height),
+            Instruction::X(x) => (x, self.width),
+        };
+        if line >= size {
+            return Err(FoldError::OutsidePaper { instruction, size });
         }
 
-        let (folded_width, folded_height) = instructions.iter().fold(
-            (self.folded_width, self.folded_height),
-            |(width, height), instr| match *instr {
-                Instruction::Y(y) => (width, y),
-                Instruction::X(x) => (x, height),
+        let on_line = |p: &Point| match instruction {
+            Instruction::Y(y) => p.y == y,
+            Instruction::X(x) => p.x == x,
+        };
+        let points = self.points();
+        let dots = points.iter().filter(|p| on_line(p)).count();
+        if dots > 0 {
+            return Err(FoldError::DotsOnLine { instruction, dots });
+        }
+
+        let folded_len = line.max(size - line - 1);
+        let (width, height) = match instruction {
+            Instruction::Y(_) => (self.width, folded_len),
+            Instruction::X(_) => (folded_len, self.height),
+        };
+        let folded = points.into_iter().map(|p| match instruction {
+            Instruction::Y(y) => Point {
+                y: fold_coord(p.y, y, folded_len),
+                ..p
             },
-        );
+            Instruction::X(x) => Point {
+                x: fold_coord(p.x, x, folded_len),
+                ..p
+            },
+        });
+
+        match &mut self.dots {
+            Dots::Sparse(dots) => *dots = folded.collect(),
+            Dots::Dense(dots) => {
+                dots.clear();
+                dots.resize(width * height, false);
+                for p in folded {
+                    dots[p.y * width + p.x] = true;
+                }
+            }
+        }
+        self.width = width;
+        self.height = height;
 
-        self.folded_width = folded_width;
-        self.folded_height = folded_height;
+        Ok(())
     }
 
-    fn count_dots(&self) -> usize {
-        self.folded.len()
+    fn make_folds(&mut self, instructions: &[Instruction]) -> Result<(), FoldError> {
+        instructions.iter().try_for_each(|&instr| self.fold(instr))
     }
-}
 
-#[derive(Debug, Clone)]
-struct PaperInstructionsPart2 {
-    points: Vec<Point>,
-    folded: Vec<bool>,
-    width: usize,
-    height: usize,
-}
-
-impl PaperInstructionsPart2 {
-    fn parse(input: &str) -> (Self, Vec<Instruction>) {
-        let (point_str, instr_str) = input.split_once("\n\n").expect("Segment break not found");
-        let points: Vec<_> = point_str.trim().lines().map(Point::parse).collect();
-        let instrs = instr_str.trim().lines().map(Instruction::parse).collect();
-
-        let (width, height) = points
+    /// Returns the sheet as it is after each fold.
+    fn replay(&self, instructions: &[Instruction]) -> Result<Vec<Paper>, FoldError> {
+        let mut paper = self.clone();
+        instructions
             .iter()
-            .fold((0, 0), |(x, y), p| (x.max(p.x + 1), y.max(p.y + 1)));
+            .map(|&instr| {
+                paper.fold(instr)?;
+                Ok(paper.clone())
+            })
+            .collect()
+    }
 
-        (
-            Self {
-                points,
-                folded: Vec::new(),
-                width,
-                height,
-            },
-            instrs,
-        )
+    fn count_dots(&self) -> usize {
+        match &self.dots {
+            Dots::Sparse(dots) => dots.len(),
+            Dots::Dense(dots) => dots.iter().filter(|p| **p).count(),
+        }
+    }
+
+    fn to_dense(&self) -> Vec<bool> {
+        match &self.dots {
+            Dots::Sparse(_) => (0..self.height)
+                .flat_map(|y| (0..self.width).map(move |x| Point { x, y }))
+                .map(|p| self.contains(p))
+                .collect(),
+            Dots::Dense(dots) => dots.clone(),
+        }
     }
 
     fn print_paper(&self) -> String {
         let mut output = String::new();
 
-        for row in self.folded.chunks_exact(self.width) {
+        for row in self.to_dense().chunks_exact(self.width) {
             for &p in row {
                 output.push(if p { '#' } else { ' ' });
             }
@@ -209,35 +338,213 @@ impl PaperInstructionsPart2 {
 
         output
     }
+}
 
-    fn make_folds(&mut self, instructions: &[Instruction]) {
-        let (folded_width, folded_height) =
-            instructions
-                .iter()
-                .fold(
-                    (self.width, self.height),
-                    |(width, height), instr| match *instr {
-                        Instruction::Y(y) => (width, y),
-                        Instruction::X(x) => (x, height),
-                    },
-                );
-
-        self.folded.resize(folded_width * folded_height, false);
-        self.width = folded_width;
-        self.height = folded_height;
-
-        for &point in &self.points {
-            let new_point = instructions
-                .iter()
-                .fold(point, |p, instr| p.reflect(*instr));
-            self.folded[new_point.y * folded_width + new_point.x] = true;
+fn render_replay(paper: &Paper, instructions: &[Instruction]) -> Result<String, FoldError> {
+    let mut output = String::new();
+    for (instr, sheet) in instructions.iter().zip(paper.replay(instructions)?) {
+        output.push_str(&format!("{}:\n", instr));
+        output.push_str(&sheet.print_paper());
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
+/// Every position on the unfolded sheet each folded dot could have come from.
+#[derive(Debug, Clone)]
+#[allow(unused)]
+struct Unfolding {
+    width: usize,
+    height: usize,
+    candidates: Vec<Vec<Point>>,
+}
+
+impl Unfolding {
+    /// Works back through the folds, assuming each fold was made with the paper's far
+    /// edge meeting the near edge whenever the folded size allows it.
+    #[allow(unused)]
+    fn new(folded: &Paper, instructions: &[Instruction]) -> Result<Self, FoldError> {
+        let mut width = folded.width;
+        let mut height = folded.height;
+        let mut candidates: Vec<_> = folded.points().into_iter().map(|p| vec![p]).collect();
+        candidates.sort_unstable_by_key(|c| (c[0].y, c[0].x));
+
+        for &instruction in instructions.iter().rev() {
+            let (line, folded_len) = match instruction {
+                Instruction::Y(y) => (y, height),
+                Instruction::X(x) => (x, width),
+            };
+            if line > folded_len {
+                return Err(FoldError::CannotUnfold {
+                    instruction,
+                    size: folded_len,
+                });
+            }
+            let size = line + folded_len + 1;
+
+            let unfold_coord = move |coord: usize| {
+                let dist = folded_len - coord;
+                let near = line.checked_sub(dist);
+                let far = Some(line + dist).filter(|&c| c < size);
+                near.into_iter().chain(far)
+            };
+
+            for points in &mut candidates {
+                let mut unfolded = Vec::with_capacity(points.len() * 2);
+                for &p in points.iter() {
+                    match instruction {
+                        Instruction::Y(_) => {
+                            unfolded.extend(unfold_coord(p.y).map(|y| Point { y, ..p }))
+                        }
+                        Instruction::X(_) => {
+                            unfolded.extend(unfold_coord(p.x).map(|x| Point { x, ..p }))
+                        }
+                    }
+                }
+                *points = unfolded;
+            }
+
+            match instruction {
+                Instruction::Y(_) => height = size,
+                Instruction::X(_) => width = size,
+            }
         }
+
+        Ok(Self {
+            width,
+            height,
+            candidates,
+        })
     }
 
+    /// Enumerates the smallest sets of dots which fold into the pattern, which use
+    /// exactly one of the candidates for each folded dot.
     #[allow(unused)]
-    fn count_dots(&self) -> usize {
-        self.folded.iter().filter(|p| **p).count()
+    fn minimal_sets(&self) -> impl Iterator<Item = Vec<Point>> + '_ {
+        let mut choice = vec![0; self.candidates.len()];
+        let mut done = self.candidates.iter().any(|c| c.is_empty());
+
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+
+            let set = (self.candidates.iter())
+                .zip(&choice)
+                .map(|(c, &i)| c[i])
+                .collect();
+
+            // Step the choices like an odometer, with each digit a different base.
+            done = true;
+            for (digit, c) in choice.iter_mut().zip(&self.candidates) {
+                *digit += 1;
+                if *digit < c.len() {
+                    done = false;
+                    break;
+                }
+                *digit = 0;
+            }
+
+            Some(set)
+        })
+    }
+}
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+/// Glyphs are separated by a single blank column.
+const GLYPH_PITCH: usize = GLYPH_WIDTH + 1;
+
+/// The block-letter font the folded paper is written in.
+const FONT: &[(char, [&str; GLYPH_HEIGHT])] = &[
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OcrError {
+    BadHeight(usize),
+    UnknownGlyph { index: usize, bitmap: String },
+}
+
+impl Display for OcrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OcrError::BadHeight(height) => write!(
+                f,
+                "paper is {} dots high, but letters are {} high",
+                height, GLYPH_HEIGHT
+            ),
+            OcrError::UnknownGlyph { index, bitmap } => {
+                writeln!(f, "unknown glyph at letter {}:", index)?;
+                f.write_str(bitmap)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OcrError {}
+
+/// Packs a glyph into a bitmask, one bit per dot in row-major order.
+fn glyph_bits(rows: impl Iterator<Item = impl Iterator<Item = bool>>) -> u32 {
+    rows.flatten().fold(0, |bits, dot| (bits << 1) | dot as u32)
+}
+
+/// Reads the letters written on a folded sheet, `width` dots across and `height` down.
+fn read_letters(dots: &[bool], width: usize, height: usize) -> Result<String, OcrError> {
+    if height != GLYPH_HEIGHT {
+        return Err(OcrError::BadHeight(height));
+    }
+
+    let mut letters = String::new();
+    for (index, left) in (0..width)
+        .step_by(GLYPH_PITCH)
+        .take_while(|left| left + GLYPH_WIDTH <= width)
+        .enumerate()
+    {
+        let glyph = || {
+            dots.chunks_exact(width)
+                .map(move |row| row[left..left + GLYPH_WIDTH].iter().copied())
+        };
+
+        let bits = glyph_bits(glyph());
+        let letter = FONT
+            .iter()
+            .find(|(_, rows)| {
+                let rows = rows.iter().map(|r| r.bytes().map(|b| b == b'#'));
+                bits == glyph_bits(rows)
+            })
+            .map(|&(letter, _)| letter);
+
+        match letter {
+            Some(letter) => letters.push(letter),
+            None => {
+                let mut bitmap = String::new();
+                for row in glyph() {
+                    bitmap.extend(row.map(|dot| if dot { '#' } else { '.' }));
+                    bitmap.push('\n');
+                }
+                return Err(OcrError::UnknownGlyph { index, bitmap });
+            }
+        }
     }
+
+    Ok(letters)
 }
 
 #[cfg(test)]
@@ -252,14 +559,15 @@ mod tests_template {
             .open()
             .unwrap();
 
-        let (mut paper, instrs) = PaperInstructionsPart1::parse(&input);
+        let (points, instrs) = parse(&input);
+        let mut paper = Paper::new(&points, Backend::Sparse);
         let mut p1_paper = paper.clone();
 
-        p1_paper.make_folds(&instrs[..1]);
+        p1_paper.make_folds(&instrs[..1]).unwrap();
         println!("{}", p1_paper.print_paper());
         assert_eq!(17, p1_paper.count_dots());
 
-        paper.make_folds(&instrs);
+        paper.make_folds(&instrs).unwrap();
         println!("{}", paper.print_paper());
         assert_eq!(16, paper.count_dots());
     }
@@ -271,16 +579,145 @@ mod tests_template {
             .open()
             .unwrap();
 
-        let (mut paper, instrs) = PaperInstructionsPart2::parse(&input);
+        let (points, instrs) = parse(&input);
+        let mut paper = Paper::new(&points, Backend::Dense);
 
         let mut p1_paper = paper.clone();
 
-        p1_paper.make_folds(&instrs[..1]);
+        p1_paper.make_folds(&instrs[..1]).unwrap();
         println!("{}", p1_paper.print_paper());
         assert_eq!(17, p1_paper.count_dots());
 
-        paper.make_folds(&instrs);
+        paper.make_folds(&instrs).unwrap();
         println!("{}", paper.print_paper());
         assert_eq!(16, paper.count_dots());
     }
+
+    #[test]
+    fn asymmetric_fold_test() {
+        // Folding at x=1 leaves three columns to the right, so the single column on the
+        // left lands at the far edge of the folded sheet.
+        let points = [
+            Point { x: 0, y: 0 },
+            Point { x: 2, y: 0 },
+            Point { x: 4, y: 1 },
+        ];
+
+        for backend in [Backend::Sparse, Backend::Dense] {
+            let mut paper = Paper::new(&points, backend);
+            paper.fold(Instruction::X(1)).unwrap();
+            assert_eq!((3, 2), (paper.width, paper.height));
+            assert_eq!("  #\n#  \n", paper.print_paper());
+
+            let mut paper = Paper::new(&points, backend);
+            let instruction = Instruction::X(4);
+            let expected = FoldError::DotsOnLine {
+                instruction,
+                dots: 1,
+            };
+            assert_eq!(Err(expected), paper.fold(instruction));
+
+            let instruction = Instruction::Y(2);
+            let expected = FoldError::OutsidePaper {
+                instruction,
+                size: 2,
+            };
+            assert_eq!(Err(expected), paper.fold(instruction));
+        }
+    }
+
+    #[test]
+    fn replay_test() {
+        let input = aoc_lib::input(13)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let (points, instrs) = parse(&input);
+        let paper = Paper::new(&points, Backend::Sparse);
+        let sheets = paper.replay(&instrs).unwrap();
+        let sizes: Vec<_> = sheets
+            .iter()
+            .map(|s| (s.width, s.height, s.count_dots()))
+            .collect();
+        assert_eq!(vec![(11, 7, 17), (5, 7, 16)], sizes);
+
+        let replay = render_replay(&paper, &instrs).unwrap();
+        assert!(replay.starts_with("fold along y=7:\n"));
+        assert!(replay.contains("fold along x=5:\n#####\n#   #\n"));
+    }
+
+    #[test]
+    fn unfold_test() {
+        let input = aoc_lib::input(13)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let (points, instrs) = parse(&input);
+        let mut folded = Paper::new(&points, Backend::Sparse);
+        folded.make_folds(&instrs).unwrap();
+
+        // The example unfolds back onto its original 11x15 sheet.
+        let unfolding = Unfolding::new(&folded, &instrs).unwrap();
+        assert_eq!((11, 15), (unfolding.width, unfolding.height));
+        assert!(unfolding.candidates.iter().all(|c| c.len() == 4));
+
+        // Every original dot is one of the candidates.
+        let candidates: HashSet<_> = unfolding.candidates.iter().flatten().collect();
+        assert!(points.iter().all(|p| candidates.contains(p)));
+
+        for set in unfolding.minimal_sets().take(100) {
+            assert_eq!(16, set.len());
+
+            let (width, height) = (unfolding.width, unfolding.height);
+            let mut paper = Paper::with_size(&set, width, height, Backend::Dense);
+            paper.make_folds(&instrs).unwrap();
+            assert_eq!(folded.to_dense(), paper.to_dense());
+        }
+
+        let expected = FoldError::CannotUnfold {
+            instruction: Instruction::X(6),
+            size: 5,
+        };
+        assert_eq!(
+            expected,
+            Unfolding::new(&folded, &[Instruction::X(6)]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn read_letters_test() {
+        let rows = [
+            ".##..###..####.#..#",
+            "#..#.#..#.#....#.#.",
+            "#..#.###..###..##..",
+            "####.#..#.#....#.#.",
+            "#..#.#..#.#....#.#.",
+            "#..#.###..####.#..#",
+        ];
+        let dots: Vec<_> = rows
+            .iter()
+            .flat_map(|r| r.bytes().map(|b| b == b'#'))
+            .collect();
+        assert_eq!(Ok("ABEK".to_owned()), read_letters(&dots, 19, 6));
+
+        let mut dots = dots;
+        dots[8] = true;
+        let bitmap = "####\n#..#\n###.\n#..#\n#..#\n###.\n".to_owned();
+        let expected = OcrError::UnknownGlyph { index: 1, bitmap };
+        assert_eq!(Err(expected), read_letters(&dots, 19, 6));
+
+        let input = aoc_lib::input(13)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+        let (points, instrs) = parse(&input);
+        let mut paper = Paper::new(&points, Backend::Sparse);
+        paper.make_folds(&instrs).unwrap();
+        assert_eq!(
+            Err(OcrError::BadHeight(7)),
+            read_letters(&paper.to_dense(), paper.width, paper.height)
+        );
+    }
 }
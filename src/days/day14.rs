@@ -1,4 +1,12 @@
-use aoc_lib::{misc::ArrWindows, Bench, BenchResult, Day, NoError, ParseResult};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+    num::NonZeroU64,
+    ops::Range,
+};
+
+use aoc_lib::{misc::ArrWindows, Bench, BenchResult, Day, NoError, ParseResult, UserError};
+use lasso::{Key, Rodeo};
 
 // 13:15
 // 14:08 - Part 2
@@ -8,92 +16,951 @@ pub const DAY: Day = Day {
     name: "Extended Polymerization",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse)],
+    other: &[
+        ("Parse", run_parse),
+        ("Part 1 Expanded", run_part1_expanded),
+        ("Part 2 Matrix", run_part2_matrix),
+        ("Part 2 Counts", run_part2_counts),
+        ("Counts Modular", run_counts_modular),
+    ],
+};
+
+/// Far more steps than exact counts could manage.
+const MODULAR_STEPS: u64 = 1_000_000_000_000;
+const MODULUS: NonZeroU64 = match NonZeroU64::new(1_000_000_007) {
+    Some(modulus) => modulus,
+    None => panic!("modulus must be non-zero"),
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
-    let (template, rules) = parse(input);
-    b.bench(|| Ok::<_, NoError>(run_replace::<10>(&template, &rules)))
+    let polymer = Polymer::parse(input).map_err(UserError)?;
+    b.bench(|| Ok::<_, NoError>(run_replace(&polymer, 10)))
 }
 
 fn run_part2(input: &str, b: Bench) -> BenchResult {
-    let (template, rules) = parse(input);
-    b.bench(|| Ok::<_, NoError>(run_replace::<40>(&template, &rules)))
+    let polymer = Polymer::parse(input).map_err(UserError)?;
+    b.bench(|| Ok::<_, NoError>(run_replace(&polymer, 40)))
 }
 
-fn run_parse(input: &str, b: Bench) -> BenchResult {
-    b.bench(|| Ok::<_, NoError>(ParseResult(parse(input))))
+fn run_part1_expanded(input: &str, b: Bench) -> BenchResult {
+    let polymer = Polymer::parse(input).map_err(UserError)?;
+    b.bench(|| {
+        let mut counts = vec![0_usize; polymer.num_elements()];
+        for element in polymer.expand(10) {
+            counts[element] += 1;
+        }
+        let counts = counts.into_iter().filter(|&c| c > 0);
+        let (min, max) = counts.fold((usize::MAX, 0), |(min, max), c| (min.min(c), max.max(c)));
+
+        Ok::<_, NoError>(max.saturating_sub(min))
+    })
 }
 
-fn get_idx(pair: [u8; 2]) -> usize {
-    let pair = pair.map(|b| b as usize);
-    pair[0] * 32 + pair[1]
+fn run_part2_matrix(input: &str, b: Bench) -> BenchResult {
+    let polymer = Polymer::parse(input).map_err(UserError)?;
+    b.bench(|| {
+        let counts = element_counts(&polymer, 40, &Exact);
+        Ok::<_, NoError>(quantity_difference(&counts))
+    })
 }
 
-const NO_RULE: u8 = 255;
+fn run_part2_counts(input: &str, b: Bench) -> BenchResult {
+    let polymer = Polymer::parse(input).map_err(UserError)?;
+    b.bench_alt(|| Ok::<_, NoError>(render_counts(&polymer, 40, &Exact)))
+}
 
-fn parse(input: &str) -> (Vec<u8>, Vec<u8>) {
-    let (template, rest) = input.trim().split_once('\n').expect("bad input");
+fn run_counts_modular(input: &str, b: Bench) -> BenchResult {
+    let polymer = Polymer::parse(input).map_err(UserError)?;
+    b.bench_alt(|| {
+        let arith = Modular(MODULUS);
+        Ok::<_, NoError>(render_counts(&polymer, MODULAR_STEPS, &arith))
+    })
+}
 
-    let mut rules_lookup = vec![NO_RULE; 32 * 32];
-    for line in rest.trim().lines() {
-        let (pattern, insertion) = line.trim().split_once(" -> ").expect("bad input");
-        assert!(insertion.len() == 1);
-        let [a, b]: [u8; 2] = pattern.as_bytes().try_into().expect("bad input");
-        let pattern_idx = get_idx([a - b'A', b - b'A']);
+fn run_parse(input: &str, b: Bench) -> BenchResult {
+    b.bench(|| Polymer::parse(input).map(ParseResult))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PolymerError {
+    MissingRules,
+    BadLine {
+        line: usize,
+        text: String,
+    },
+    /// A rule's pattern wasn't exactly two elements.
+    BadPattern {
+        line: usize,
+        pattern: String,
+    },
+    /// A rule's insertion wasn't exactly one element.
+    BadInsertion {
+        line: usize,
+        insertion: String,
+    },
+    /// An element is inserted which is in neither the template nor any pattern.
+    UnknownElement {
+        line: usize,
+        element: String,
+    },
+    ConflictingRules {
+        line: usize,
+        first_line: usize,
+    },
+}
 
-        rules_lookup[pattern_idx] = insertion.as_bytes()[0] - b'A';
+impl Display for PolymerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolymerError::MissingRules => f.write_str("no insertion rules found"),
+            PolymerError::BadLine { line, text } => {
+                write!(f, "line {}: invalid rule `{}`", line, text)
+            }
+            PolymerError::BadPattern { line, pattern } => {
+                write!(
+                    f,
+                    "line {}: pattern `{}` isn't a pair of elements",
+                    line, pattern
+                )
+            }
+            PolymerError::BadInsertion { line, insertion } => {
+                write!(
+                    f,
+                    "line {}: insertion `{}` isn't a single element",
+                    line, insertion
+                )
+            }
+            PolymerError::UnknownElement { line, element } => {
+                write!(f, "line {}: unknown element `{}`", line, element)
+            }
+            PolymerError::ConflictingRules { line, first_line } => write!(
+                f,
+                "line {}: rule conflicts with the rule on line {}",
+                line, first_line
+            ),
+        }
     }
+}
 
-    let template = template.bytes().map(|b| b - b'A').collect();
-    (template, rules_lookup)
+impl std::error::Error for PolymerError {}
+
+/// Splits a formula into its element symbols. Each symbol is a single character
+/// followed by any number of lowercase letters, so `FeCl` is `Fe` and `Cl`.
+fn split_elements(formula: &str) -> impl Iterator<Item = &str> {
+    let mut rest = formula;
+    std::iter::from_fn(move || {
+        let mut chars = rest.char_indices();
+        chars.next()?;
+        let end = chars
+            .find(|(_, c)| !c.is_ascii_lowercase())
+            .map_or(rest.len(), |(idx, _)| idx);
+
+        let (symbol, tail) = rest.split_at(end);
+        rest = tail;
+        Some(symbol)
+    })
 }
 
-fn run_replace<const N: usize>(template: &[u8], rules: &[u8]) -> usize {
-    let mut pairs = vec![0_usize; 32 * 32];
+/// A polymer template and its insertion rules, with elements interned into a dense
+/// alphabet.
+#[derive(Debug)]
+struct Polymer {
+    elements: Rodeo,
+    template: Vec<usize>,
+    /// The element inserted between each pair, indexed by `pair_idx`.
+    rules: Vec<Option<usize>>,
+}
+
+impl Polymer {
+    fn parse(input: &str) -> Result<Self, PolymerError> {
+        let (template, rest) = input
+            .trim()
+            .split_once('\n')
+            .ok_or(PolymerError::MissingRules)?;
+
+        let mut elements: Rodeo = Rodeo::new();
+        let template: Vec<_> = split_elements(template.trim())
+            .map(|e| elements.get_or_intern(e).into_usize())
+            .collect();
 
-    for &pair in ArrWindows::new(template) {
-        let idx = get_idx(pair);
-        pairs[idx] += 1;
+        // Intern the whole alphabet first, so the table can be sized from it.
+        let mut parsed = Vec::new();
+        for (line, text) in rest.trim().lines().enumerate() {
+            // The template and the blank line come first.
+            let line = line + 3;
+            let (pattern, insertion) =
+                text.trim()
+                    .split_once(" -> ")
+                    .ok_or_else(|| PolymerError::BadLine {
+                        line,
+                        text: text.to_owned(),
+                    })?;
+
+            let pair = match *split_elements(pattern).collect::<Vec<_>>() {
+                [a, b] => [a, b].map(|e| elements.get_or_intern(e).into_usize()),
+                _ => {
+                    return Err(PolymerError::BadPattern {
+                        line,
+                        pattern: pattern.to_owned(),
+                    })
+                }
+            };
+            if split_elements(insertion).count() != 1 {
+                return Err(PolymerError::BadInsertion {
+                    line,
+                    insertion: insertion.to_owned(),
+                });
+            }
+
+            parsed.push((line, pair, insertion));
+        }
+
+        let num_elements = elements.len();
+        let mut rules = vec![None; num_elements * num_elements];
+        let mut rule_lines = vec![0; num_elements * num_elements];
+        for (line, [a, b], insertion) in parsed {
+            let insertion = elements
+                .get(insertion)
+                .ok_or_else(|| PolymerError::UnknownElement {
+                    line,
+                    element: insertion.to_owned(),
+                })?
+                .into_usize();
+
+            let idx = a * num_elements + b;
+            match rules[idx] {
+                Some(existing) if existing != insertion => {
+                    return Err(PolymerError::ConflictingRules {
+                        line,
+                        first_line: rule_lines[idx],
+                    })
+                }
+                _ => {
+                    rules[idx] = Some(insertion);
+                    rule_lines[idx] = line;
+                }
+            }
+        }
+
+        Ok(Self {
+            elements,
+            template,
+            rules,
+        })
     }
 
-    pairs[get_idx([template[template.len() - 1], 26])] += 1;
+    fn num_elements(&self) -> usize {
+        self.elements.len()
+    }
 
-    let mut dst = vec![0_usize; 32 * 32];
+    fn pair_idx(&self, a: usize, b: usize) -> usize {
+        a * self.num_elements() + b
+    }
 
-    for _ in 0..N {
+    fn element_name(&self, element: usize) -> &str {
+        self.elements
+            .resolve(&Key::try_from_usize(element).unwrap())
+    }
+
+    /// Builds the whole polymer after the given number of steps. It roughly doubles in
+    /// length each step, so this is only useful for a handful of them.
+    fn expand(&self, steps: usize) -> Vec<usize> {
+        let mut polymer = self.template.clone();
+        for _ in 0..steps {
+            let mut next = Vec::with_capacity(polymer.len() * 2);
+            for &[a, b] in ArrWindows::new(&polymer) {
+                next.push(a);
+                next.extend(self.rules[self.pair_idx(a, b)]);
+            }
+            next.extend(polymer.last());
+            polymer = next;
+        }
+
+        polymer
+    }
+
+    #[allow(unused)]
+    fn render(&self, polymer: &[usize]) -> String {
+        polymer.iter().map(|&e| self.element_name(e)).collect()
+    }
+}
+
+fn run_replace(polymer: &Polymer, steps: usize) -> usize {
+    let num_elements = polymer.num_elements();
+    let mut pairs = vec![0_usize; num_elements * num_elements];
+
+    for &[a, b] in ArrWindows::new(&polymer.template) {
+        pairs[polymer.pair_idx(a, b)] += 1;
+    }
+
+    let mut dst = vec![0_usize; pairs.len()];
+
+    for _ in 0..steps {
         dst.fill(0);
         for (pair, &count) in pairs.iter().enumerate() {
             if count == 0 {
                 continue;
             }
-            let (a, b) = (pair / 32, pair % 32);
-            let replace = unsafe { *rules.get_unchecked(pair) };
-            if replace != NO_RULE {
-                let a_idx = get_idx([a as u8, replace]);
-                let b_idx = get_idx([replace, b as u8]);
-                unsafe {
-                    *dst.get_unchecked_mut(a_idx) += count;
-                    *dst.get_unchecked_mut(b_idx) += count;
+            let (a, b) = (pair / num_elements, pair % num_elements);
+            match polymer.rules[pair] {
+                Some(insert) => {
+                    dst[polymer.pair_idx(a, insert)] += count;
+                    dst[polymer.pair_idx(insert, b)] += count;
                 }
-            } else {
-                unsafe { *dst.get_unchecked_mut(pair) = count };
+                None => dst[pair] += count,
             }
         }
 
         std::mem::swap(&mut pairs, &mut dst);
     }
 
-    let mut counts = [0_usize; 32];
+    // Each element is counted as the start of a pair, apart from the last one, which
+    // never moves.
+    let mut counts = vec![0_usize; num_elements];
     for (pair, count) in pairs.into_iter().enumerate() {
-        counts[pair / 32] += count;
+        counts[pair / num_elements] += count;
+    }
+    if let Some(&last) = polymer.template.last() {
+        counts[last] += 1;
     }
 
     let (min, max) = counts.into_iter().fold((usize::MAX, 0), |(min, max), b| {
         (if b > 0 { min.min(b) } else { min }, max.max(b))
     });
 
-    max - min
+    max.saturating_sub(min)
+}
+
+/// The operations needed to count pairs, so the counts can be kept in whatever
+/// representation suits the number of steps.
+trait Arithmetic {
+    type Value: Clone;
+
+    fn zero(&self) -> Self::Value;
+    fn one(&self) -> Self::Value;
+    fn add(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+    fn mul(&self, a: &Self::Value, b: &Self::Value) -> Self::Value;
+}
+
+/// Counts modulo some number.
+#[derive(Debug, Clone, Copy)]
+struct Modular(NonZeroU64);
+
+impl Arithmetic for Modular {
+    type Value = u64;
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn one(&self) -> u64 {
+        1 % self.0.get()
+    }
+
+    fn add(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 + *b as u128) % self.0.get() as u128) as u64
+    }
+
+    fn mul(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 * *b as u128) % self.0.get() as u128) as u64
+    }
+}
+
+/// Exact counts, however big they get.
+#[derive(Debug, Clone, Copy)]
+struct Exact;
+
+impl Arithmetic for Exact {
+    type Value = BigUint;
+
+    fn zero(&self) -> BigUint {
+        BigUint::from(0)
+    }
+
+    fn one(&self) -> BigUint {
+        BigUint::from(1)
+    }
+
+    fn add(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a.add(b)
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a.mul(b)
+    }
+}
+
+/// Counts which stick at the maximum rather than overflowing.
+#[derive(Debug, Clone, Copy)]
+struct Saturating;
+
+impl Arithmetic for Saturating {
+    type Value = u128;
+
+    fn zero(&self) -> u128 {
+        0
+    }
+
+    fn one(&self) -> u128 {
+        1
+    }
+
+    fn add(&self, a: &u128, b: &u128) -> u128 {
+        a.saturating_add(*b)
+    }
+
+    fn mul(&self, a: &u128, b: &u128) -> u128 {
+        a.saturating_mul(*b)
+    }
+}
+
+/// Just enough of an arbitrary precision unsigned integer to count pairs with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BigUint {
+    /// Least significant first, with no trailing zeros.
+    limbs: Vec<u32>,
+}
+
+impl From<u64> for BigUint {
+    fn from(val: u64) -> Self {
+        let mut num = BigUint {
+            limbs: vec![val as u32, (val >> 32) as u32],
+        };
+        num.trim();
+        num
+    }
+}
+
+impl BigUint {
+    fn trim(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+    }
+
+    fn add(&self, other: &BigUint) -> BigUint {
+        let len = self.limbs.len().max(other.limbs.len());
+        let mut limbs = Vec::with_capacity(len + 1);
+        let mut carry = 0;
+        for i in 0..len {
+            let a = *self.limbs.get(i).unwrap_or(&0) as u64;
+            let b = *other.limbs.get(i).unwrap_or(&0) as u64;
+            let sum = a + b + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+
+        let mut num = BigUint { limbs };
+        num.trim();
+        num
+    }
+
+    fn mul(&self, other: &BigUint) -> BigUint {
+        let mut limbs = vec![0_u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let prod = a as u64 * b as u64 + limbs[i + j] as u64 + carry;
+                limbs[i + j] = prod as u32;
+                carry = prod >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+
+        let mut num = BigUint { limbs };
+        num.trim();
+        num
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// Panics if `other` is bigger.
+    fn sub(&self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0;
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let b = *other.limbs.get(i).unwrap_or(&0) as u64;
+            let (diff, under) = (a as u64).overflowing_sub(b + borrow);
+            limbs.push(diff as u32);
+            borrow = under as u64;
+        }
+        assert!(
+            borrow == 0 && other.limbs.len() <= self.limbs.len(),
+            "subtraction underflowed"
+        );
+
+        let mut num = BigUint { limbs };
+        num.trim();
+        num
+    }
+
+    #[allow(unused)]
+    fn to_u128(&self) -> Option<u128> {
+        if self.limbs.len() > 4 {
+            return None;
+        }
+        Some(
+            self.limbs
+                .iter()
+                .rev()
+                .fold(0, |acc, &l| (acc << 32) | l as u128),
+        )
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // With no trailing zeros, more limbs always means bigger.
+        (self.limbs.len().cmp(&other.limbs.len()))
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigUint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const CHUNK: u64 = 1_000_000_000;
+
+        // Peel off nine decimal digits at a time, least significant first.
+        let mut limbs = self.limbs.clone();
+        let mut chunks = Vec::new();
+        while !limbs.is_empty() {
+            let mut rem = 0;
+            for limb in limbs.iter_mut().rev() {
+                let cur = (rem << 32) | *limb as u64;
+                *limb = (cur / CHUNK) as u32;
+                rem = cur % CHUNK;
+            }
+            chunks.push(rem);
+            while limbs.last() == Some(&0) {
+                limbs.pop();
+            }
+        }
+
+        match chunks.split_last() {
+            None => f.write_str("0"),
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                rest.iter().rev().try_for_each(|c| write!(f, "{:09}", c))
+            }
+        }
+    }
+}
+
+/// A square matrix over pair counts.
+#[derive(Debug, Clone)]
+struct Matrix<T> {
+    size: usize,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Matrix<T> {
+    fn mul<A: Arithmetic<Value = T>>(&self, other: &Self, arith: &A) -> Self {
+        let size = self.size;
+        let mut cells = vec![arith.zero(); size * size];
+        for row in 0..size {
+            for k in 0..size {
+                let a = &self.cells[row * size + k];
+                for col in 0..size {
+                    let b = &other.cells[k * size + col];
+                    let cell = &mut cells[row * size + col];
+                    *cell = arith.add(cell, &arith.mul(a, b));
+                }
+            }
+        }
+
+        Self { size, cells }
+    }
+
+    fn transpose(&self) -> Self {
+        let size = self.size;
+        let cells = (0..size * size)
+            .map(|idx| self.cells[(idx % size) * size + idx / size].clone())
+            .collect();
+
+        Self { size, cells }
+    }
+
+    fn mul_vec<A: Arithmetic<Value = T>>(&self, vec: &[T], arith: &A) -> Vec<T> {
+        // A template of one element has no pairs at all.
+        if self.size == 0 {
+            return Vec::new();
+        }
+
+        (self.cells.chunks_exact(self.size))
+            .map(|row| {
+                (row.iter().zip(vec)).fold(arith.zero(), |acc, (a, b)| {
+                    arith.add(&acc, &arith.mul(a, b))
+                })
+            })
+            .collect()
+    }
+}
+
+/// The pairs which can ever appear starting from the template, so the matrices only
+/// need to cover those rather than every pair of elements.
+struct ReachablePairs {
+    /// Pair indices, in the order the matrix rows use.
+    pairs: Vec<usize>,
+    /// Maps a pair index to its row.
+    rows: HashMap<usize, usize>,
+}
+
+impl ReachablePairs {
+    fn new(polymer: &Polymer) -> Self {
+        let mut reachable = ReachablePairs {
+            pairs: Vec::new(),
+            rows: HashMap::new(),
+        };
+        for &[a, b] in ArrWindows::new(&polymer.template) {
+            reachable.insert(polymer.pair_idx(a, b));
+        }
+
+        // The pairs list doubles as the queue.
+        let mut next = 0;
+        while let Some(&pair) = reachable.pairs.get(next) {
+            next += 1;
+            for target in pair_targets(polymer, pair) {
+                reachable.insert(target);
+            }
+        }
+
+        reachable
+    }
+
+    fn insert(&mut self, pair: usize) -> usize {
+        let pairs = &mut self.pairs;
+        *self.rows.entry(pair).or_insert_with(|| {
+            pairs.push(pair);
+            pairs.len() - 1
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.pairs.len()
+    }
+}
+
+/// The pairs a pair turns into after one step.
+fn pair_targets(polymer: &Polymer, pair: usize) -> Vec<usize> {
+    let num_elements = polymer.num_elements();
+    let (a, b) = (pair / num_elements, pair % num_elements);
+    match polymer.rules[pair] {
+        Some(insert) => vec![polymer.pair_idx(a, insert), polymer.pair_idx(insert, b)],
+        None => vec![pair],
+    }
+}
+
+/// Builds the matrix which takes the pair counts from one step to the next.
+fn transition_matrix<A: Arithmetic>(
+    polymer: &Polymer,
+    reachable: &ReachablePairs,
+    arith: &A,
+) -> Matrix<A::Value> {
+    let size = reachable.len();
+    let mut cells = vec![arith.zero(); size * size];
+
+    let one = arith.one();
+    for (col, &pair) in reachable.pairs.iter().enumerate() {
+        for target in pair_targets(polymer, pair) {
+            let cell = &mut cells[reachable.rows[&target] * size + col];
+            *cell = arith.add(cell, &one);
+        }
+    }
+
+    Matrix { size, cells }
+}
+
+/// Counts each element after any number of steps, indexed by interned element.
+fn element_counts<A: Arithmetic>(polymer: &Polymer, steps: u64, arith: &A) -> Vec<A::Value> {
+    let reachable = ReachablePairs::new(polymer);
+    let mut pairs = vec![arith.zero(); reachable.len()];
+    for &[a, b] in ArrWindows::new(&polymer.template) {
+        let pair = &mut pairs[reachable.rows[&polymer.pair_idx(a, b)]];
+        *pair = arith.add(pair, &arith.one());
+    }
+
+    // Exponentiation by squaring, applying each power straight to the counts.
+    let mut power = transition_matrix(polymer, &reachable, arith);
+    let mut steps = steps;
+    while steps > 0 {
+        if steps & 1 == 1 {
+            pairs = power.mul_vec(&pairs, arith);
+        }
+        steps >>= 1;
+        if steps > 0 {
+            power = power.mul(&power, arith);
+        }
+    }
+
+    let num_elements = polymer.num_elements();
+    let mut counts = vec![arith.zero(); num_elements];
+    for (&pair, count) in reachable.pairs.iter().zip(&pairs) {
+        let first = &mut counts[pair / num_elements];
+        *first = arith.add(first, count);
+    }
+    if let Some(&last) = polymer.template.last() {
+        counts[last] = arith.add(&counts[last], &arith.one());
+    }
+
+    counts
+}
+
+/// Lists the count of each element after `steps` steps, one per line.
+fn render_counts<A: Arithmetic>(polymer: &Polymer, steps: u64, arith: &A) -> String
+where
+    A::Value: Display,
+{
+    let counts = element_counts(polymer, steps, arith);
+    let mut output = String::new();
+    for (element, count) in counts.iter().enumerate() {
+        // Writing to a String can't fail.
+        writeln!(output, "{}: {}", polymer.element_name(element), count).unwrap();
+    }
+
+    output
+}
+
+/// The most common element's count less the least common's, ignoring elements which
+/// never appear.
+fn quantity_difference(counts: &[BigUint]) -> BigUint {
+    let present = counts.iter().filter(|c| !c.is_zero());
+    match (present.clone().min(), present.max()) {
+        (Some(min), Some(max)) => max.sub(min),
+        _ => BigUint::from(0),
+    }
+}
+
+/// How long each pair grows, so positions in the polymer can be found without
+/// building it.
+///
+/// A pair's expansion includes its first element but not its last, so the expansions
+/// of a polymer's pairs can be laid end to end. Lengths saturate rather than overflow.
+#[allow(unused)]
+struct ExpansionLengths<'a> {
+    polymer: &'a Polymer,
+    reachable: ReachablePairs,
+    /// The lengths after each of the first few steps, indexed by reachable pair.
+    levels: Vec<Vec<u128>>,
+    /// Whether the lengths stopped changing, so the last level stands in for all the
+    /// levels after it.
+    fixed: bool,
+    /// Takes lengths `2^k` steps further than the last level, for when the lengths keep
+    /// growing past it. Only as many as the steps asked for need are built.
+    powers: Vec<Matrix<u128>>,
+}
+
+#[allow(unused)]
+impl<'a> ExpansionLengths<'a> {
+    /// Lengths are kept for at most this many steps one by one, with anything further
+    /// found from the powers.
+    const MAX_LEVELS: u64 = 1024;
+
+    fn new(polymer: &'a Polymer, steps: u64) -> Self {
+        let reachable = ReachablePairs::new(polymer);
+        let mut levels = vec![vec![1_u128; reachable.len()]];
+        let mut fixed = false;
+
+        for _ in 0..steps.min(Self::MAX_LEVELS) {
+            let prev = levels.last().unwrap();
+            let next: Vec<_> = (reachable.pairs.iter())
+                .map(|&pair| {
+                    (pair_targets(polymer, pair).into_iter())
+                        .map(|target| prev[reachable.rows[&target]])
+                        .fold(0, u128::saturating_add)
+                })
+                .collect();
+
+            if &next == prev {
+                fixed = true;
+                break;
+            }
+            levels.push(next);
+        }
+
+        let mut powers = Vec::new();
+        let remaining = steps - (levels.len() as u64 - 1);
+        if !fixed && remaining > 0 {
+            // Lengths pull from the pairs a pair turns into, the other way round to counts.
+            powers.push(transition_matrix(polymer, &reachable, &Saturating).transpose());
+            while powers.len() < (u64::BITS - remaining.leading_zeros()) as usize {
+                let power = powers.last().unwrap();
+                powers.push(power.mul(power, &Saturating));
+            }
+        }
+
+        Self {
+            polymer,
+            reachable,
+            levels,
+            fixed,
+            powers,
+        }
+    }
+
+    fn len(&self, steps: u64, pair: usize) -> u128 {
+        let row = self.reachable.rows[&pair];
+        let last = self.levels.len() as u64 - 1;
+        if steps <= last || self.fixed {
+            return self.levels[steps.min(last) as usize][row];
+        }
+
+        let mut lengths = self.levels[last as usize].clone();
+        let remaining = steps - last;
+        for (bit, power) in self.powers.iter().enumerate() {
+            if remaining & (1 << bit) != 0 {
+                lengths = power.mul_vec(&lengths, &Saturating);
+            }
+        }
+
+        lengths[row]
+    }
+
+    fn polymer_len(&self, steps: u64) -> u64 {
+        let template = &self.polymer.template;
+        let len = ArrWindows::new(template)
+            .map(|&[a, b]| self.len(steps, self.polymer.pair_idx(a, b)))
+            .fold(template.len().min(1) as u128, u128::saturating_add);
+
+        len.min(u64::MAX as u128) as u64
+    }
+
+    /// Finds the element at the given position after `steps` steps. Steps can't be
+    /// more than those the lengths were built for.
+    fn element_at(&self, steps: u64, mut index: u64) -> Option<usize> {
+        let polymer = self.polymer;
+        for &[a, b] in ArrWindows::new(&polymer.template) {
+            let pair = polymer.pair_idx(a, b);
+            let len = self.len(steps, pair);
+            if (index as u128) < len {
+                return Some(self.descend(pair, steps, index));
+            }
+            index -= len as u64;
+        }
+
+        polymer.template.last().copied().filter(|_| index == 0)
+    }
+
+    /// The pair reached by taking the left (or right) pair `count` times, or `None` if
+    /// a pair with no rule is reached first. Chains of pairs always end up in a cycle,
+    /// so this doesn't need to walk all of them.
+    fn chain(&self, mut pair: usize, left: bool, count: u64) -> Option<usize> {
+        let polymer = self.polymer;
+        let num_elements = polymer.num_elements();
+        let mut seen = HashMap::new();
+        let mut chain = Vec::new();
+
+        for taken in 0..count {
+            if let Some(start) = seen.insert(pair, taken) {
+                let cycle = taken - start;
+                return Some(chain[(start + (count - start) % cycle) as usize]);
+            }
+            chain.push(pair);
+
+            let (a, b) = (pair / num_elements, pair % num_elements);
+            let insert = polymer.rules[pair]?;
+            pair = match left {
+                true => polymer.pair_idx(a, insert),
+                false => polymer.pair_idx(insert, b),
+            };
+        }
+
+        Some(pair)
+    }
+
+    fn descend(&self, mut pair: usize, mut steps: u64, mut index: u64) -> usize {
+        let polymer = self.polymer;
+        let num_elements = polymer.num_elements();
+
+        while steps > 0 {
+            let (a, b) = (pair / num_elements, pair % num_elements);
+            let insert = match polymer.rules[pair] {
+                Some(insert) => insert,
+                None => break,
+            };
+            let left = polymer.pair_idx(a, insert);
+            let left_len = self.len(steps - 1, left);
+            let index_len = index as u128;
+
+            if index_len < left_len {
+                // The index stays put while we keep taking the left pair, for as long as
+                // it's inside the left pair's expansion.
+                let taken = longest_run(steps, |taken| {
+                    (self.chain(pair, true, taken))
+                        .is_some_and(|next| index_len < self.len(steps - taken, next))
+                });
+                pair = self.chain(pair, true, taken).unwrap();
+                steps -= taken;
+            } else {
+                let total = self.len(steps, pair);
+                if total == u128::MAX {
+                    // Saturated, so how far into it the right pair starts isn't known.
+                    index -= left_len as u64;
+                    pair = polymer.pair_idx(insert, b);
+                    steps -= 1;
+                    continue;
+                }
+
+                // Each right pair's expansion ends the one before, so it starts however
+                // much shorter than the first it is.
+                let start = |taken, next| total - self.len(steps - taken, next);
+                let taken = longest_run(steps, |taken| {
+                    (self.chain(pair, false, taken))
+                        .is_some_and(|next| start(taken, next) <= index_len)
+                });
+                let next = self.chain(pair, false, taken).unwrap();
+                index -= start(taken, next) as u64;
+                pair = next;
+                steps -= taken;
+            }
+        }
+
+        pair / num_elements
+    }
+
+    /// Renders part of the polymer after `steps` steps, or `None` if the range runs
+    /// past the end.
+    fn substring(&self, steps: u64, range: Range<u64>) -> Option<String> {
+        range
+            .map(|idx| self.element_at(steps, idx))
+            .map(|e| e.map(|e| self.polymer.element_name(e)))
+            .collect()
+    }
+}
+
+/// The largest number up to `max` which `holds`, given that 1 does and that once one
+/// doesn't, none of the bigger ones do.
+fn longest_run(max: u64, holds: impl Fn(u64) -> bool) -> u64 {
+    let mut good = 1;
+    let mut bad = None;
+
+    // Gallop out to find one which doesn't hold, then search back in.
+    while bad.is_none() && good < max {
+        let next = good.saturating_mul(2).min(max);
+        match holds(next) {
+            true => good = next,
+            false => bad = Some(next),
+        }
+    }
+    if let Some(mut bad) = bad {
+        while bad - good > 1 {
+            let mid = good + (bad - good) / 2;
+            match holds(mid) {
+                true => good = mid,
+                false => bad = mid,
+            }
+        }
+    }
+
+    good
 }
 
 #[cfg(test)]
@@ -108,8 +975,8 @@ mod tests_template {
             .open()
             .unwrap();
 
-        let (template, rules) = parse(&input);
-        assert_eq!(1588, run_replace::<10>(&template, &rules));
+        let polymer = Polymer::parse(&input).unwrap();
+        assert_eq!(1588, run_replace(&polymer, 10));
     }
 
     #[test]
@@ -119,7 +986,209 @@ mod tests_template {
             .open()
             .unwrap();
 
-        let (template, rules) = parse(&input);
-        assert_eq!(2188189693529, run_replace::<40>(&template, &rules));
+        let polymer = Polymer::parse(&input).unwrap();
+        assert_eq!(2188189693529, run_replace(&polymer, 40));
+    }
+
+    #[test]
+    fn alphabet_test() {
+        assert_eq!(
+            vec!["Fe", "C", "Cl", "N"],
+            split_elements("FeCClN").collect::<Vec<_>>()
+        );
+
+        // The example with every element renamed to a two-letter symbol.
+        let input = aoc_lib::input(14)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+        let renamed = input
+            .replace('N', "Na")
+            .replace('C', "Ca")
+            .replace('B', "Be")
+            .replace('H', "He");
+        let polymer = Polymer::parse(&renamed).unwrap();
+        assert_eq!(4, polymer.num_elements());
+        assert_eq!(1588, run_replace(&polymer, 10));
+
+        let err = |input| Polymer::parse(input).unwrap_err();
+        let bad_pattern = PolymerError::BadPattern {
+            line: 3,
+            pattern: "Fe".to_owned(),
+        };
+        assert_eq!(bad_pattern, err("FeO\n\nFe -> O"));
+
+        let unknown = PolymerError::UnknownElement {
+            line: 3,
+            element: "Xe".to_owned(),
+        };
+        assert_eq!(unknown, err("FeO\n\nFeO -> Xe"));
+
+        let conflict = PolymerError::ConflictingRules {
+            line: 4,
+            first_line: 3,
+        };
+        assert_eq!(conflict, err("FeO\n\nFeO -> Fe\nFeO -> O"));
+    }
+
+    #[test]
+    fn matrix_test() {
+        let input = aoc_lib::input(14)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+        let polymer = Polymer::parse(&input).unwrap();
+        let element = |name| polymer.elements.get(name).unwrap().into_usize();
+
+        let counts = element_counts(&polymer, 10, &Exact);
+        assert_eq!(BigUint::from(1749), counts[element("B")]);
+        assert_eq!(BigUint::from(298), counts[element("C")]);
+        assert_eq!(BigUint::from(161), counts[element("H")]);
+        assert_eq!(BigUint::from(865), counts[element("N")]);
+
+        let counts = element_counts(&polymer, 40, &Exact);
+        assert_eq!(BigUint::from(2192039569602), counts[element("B")]);
+        assert_eq!(BigUint::from(3849876073), counts[element("H")]);
+
+        let modular = element_counts(&polymer, 40, &Modular(MODULUS));
+        for (exact, modular) in counts.iter().zip(modular) {
+            assert_eq!(exact.to_u128().unwrap() % 1_000_000_007, modular as u128);
+        }
+
+        let rendered = render_counts(&polymer, 10, &Exact);
+        assert_eq!("N: 865\nC: 298\nB: 1749\nH: 161\n", rendered);
+
+        // With only one element there are no pairs to count.
+        let lone = Polymer::parse("N\n\nNN -> N").unwrap();
+        assert_eq!(vec![BigUint::from(1)], element_counts(&lone, 40, &Exact));
+        assert_eq!(BigUint::from(0), quantity_difference(&[BigUint::from(1)]));
+        assert_eq!(
+            "N: 1\n",
+            render_counts(&lone, MODULAR_STEPS, &Modular(MODULUS))
+        );
+        assert_eq!(
+            1,
+            ExpansionLengths::new(&lone, MODULAR_STEPS).polymer_len(MODULAR_STEPS)
+        );
+
+        // The polymer doubles in length (less one) each step.
+        let length = element_counts(&polymer, 100, &Exact)
+            .iter()
+            .fold(BigUint::from(0), |acc, c| acc.add(c));
+        let expected = BigUint::from(3)
+            .mul(&BigUint::from(1 << 50))
+            .mul(&BigUint::from(1 << 50));
+        assert_eq!(
+            expected.add(&BigUint::from(1)).to_string(),
+            length.to_string()
+        );
+        assert_eq!("3802951800684688204490109616129", length.to_string());
+
+        // Too big for any primitive, so it has to be compared and subtracted exactly.
+        let huge = BigUint::from(u64::MAX).mul(&BigUint::from(u64::MAX));
+        let huge = huge.mul(&BigUint::from(10));
+        let counts = [
+            BigUint::from(5),
+            BigUint::from(0),
+            huge.clone(),
+            BigUint::from(3),
+        ];
+        assert_eq!(huge.sub(&BigUint::from(3)), quantity_difference(&counts));
+        assert!(BigUint::from(1 << 40) > BigUint::from(u32::MAX as u64));
+        assert_eq!(BigUint::from(0), quantity_difference(&[]));
+
+        // Hundreds of species, but only two pairs can ever appear.
+        let symbols: Vec<_> = (b'A'..=b'Z')
+            .flat_map(|a| (b'a'..=b'z').map(move |b| format!("{}{}", a as char, b as char)))
+            .collect();
+        let rules: String = (symbols.windows(2))
+            .map(|pair| format!("{}{} -> {}\n", pair[0], pair[1], pair[0]))
+            .collect();
+        let polymer = Polymer::parse(&format!("AaAb\n\n{}", rules)).unwrap();
+        assert_eq!(676, polymer.num_elements());
+        assert_eq!(2, ReachablePairs::new(&polymer).len());
+
+        let counts = element_counts(&polymer, 1_000_000_000_000, &Exact);
+        assert_eq!(
+            BigUint::from(1_000_000_000_000),
+            quantity_difference(&counts)
+        );
+    }
+
+    #[test]
+    fn expand_test() {
+        let input = aoc_lib::input(14)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+        let polymer = Polymer::parse(&input).unwrap();
+
+        assert_eq!("NCNBCHB", polymer.render(&polymer.expand(1)));
+        assert_eq!("NBCCNBBBCBHCB", polymer.render(&polymer.expand(2)));
+        assert_eq!(
+            "NBBNBNBBCCNBCNCCNBBNBBNBBBNBBNBBCBHCBHHNHCBBCBHCB",
+            polymer.render(&polymer.expand(4))
+        );
+        assert_eq!(3073, polymer.expand(10).len());
+    }
+
+    #[test]
+    fn element_at_test() {
+        let input = aoc_lib::input(14)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+        let polymer = Polymer::parse(&input).unwrap();
+
+        let lengths = ExpansionLengths::new(&polymer, 10);
+        for steps in 0..=10 {
+            let expanded = polymer.expand(steps as usize);
+            assert_eq!(expanded.len() as u64, lengths.polymer_len(steps));
+            for (idx, &element) in expanded.iter().enumerate() {
+                assert_eq!(Some(element), lengths.element_at(steps, idx as u64));
+            }
+            assert_eq!(None, lengths.element_at(steps, expanded.len() as u64));
+        }
+        assert_eq!(Some("CBHCB".to_owned()), lengths.substring(2, 8..13));
+        assert_eq!(None, lengths.substring(2, 8..14));
+
+        // Far past the point where the lengths saturate.
+        let steps = 1_000_000_000_000;
+        let lengths = ExpansionLengths::new(&polymer, steps);
+        assert!(lengths.fixed && lengths.powers.is_empty());
+        assert_eq!(u64::MAX, lengths.polymer_len(steps));
+        let start = lengths.substring(steps, 0..64).unwrap();
+        assert!(start.starts_with('N'));
+
+        // The start of the polymer comes from the first pair, and here its chain of left
+        // pairs settles on NB, which starts with a copy of itself a step earlier.
+        let earlier = ExpansionLengths::new(&polymer, 1000);
+        assert_eq!(Some(start), earlier.substring(1000, 0..64));
+
+        // This only grows by one each step, so it never saturates, and the alternating
+        // elements have to be found without walking every step.
+        let polymer = Polymer::parse("AB\n\nAB -> C\nCB -> A").unwrap();
+        let lengths = ExpansionLengths::new(&polymer, 20);
+        for steps in 0..=20 {
+            let expanded = polymer.render(&polymer.expand(steps as usize));
+            let len = expanded.len() as u64;
+            assert_eq!(len, lengths.polymer_len(steps));
+            assert_eq!(Some(expanded), lengths.substring(steps, 0..len));
+        }
+
+        let steps = 1_000_000_000_000;
+        let lengths = ExpansionLengths::new(&polymer, steps);
+        assert!(lengths.levels.len() as u64 <= ExpansionLengths::MAX_LEVELS + 1);
+        assert!(lengths.powers.len() <= 64);
+        assert_eq!(steps + 2, lengths.polymer_len(steps));
+        assert_eq!(Some("ACAC".to_owned()), lengths.substring(steps, 0..4));
+        let mid = steps / 2 + 1;
+        assert_eq!(
+            Some("CAC".to_owned()),
+            lengths.substring(steps, mid..mid + 3)
+        );
+        let end = lengths.substring(steps, steps - 3..steps + 2);
+        assert_eq!(Some("CACAB".to_owned()), end);
+        assert_eq!(None, lengths.element_at(steps, steps + 2));
     }
 }
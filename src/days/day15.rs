@@ -1,7 +1,12 @@
-use std::collections::BinaryHeap;
+use std::{
+    collections::{BinaryHeap, HashSet},
+    fmt::Write,
+};
 
 use aoc_lib::{Bench, BenchResult, Day, NoError, ParseResult};
 
+use crate::search::{shortest_path, Algorithm, Grid, Path, Point, SearchConfig};
+
 // 12:03
 // 12:53
 // 13:08
@@ -11,17 +16,50 @@ pub const DAY: Day = Day {
     name: "Chiton",
     part_1: run_part1,
     part_2: Some(run_part2),
-    other: &[("Parse", run_parse_part1)],
+    other: &[
+        ("Parse", run_parse_part1),
+        ("Part 1 Route", run_part1_route),
+        ("Part 2 Dijkstra", run_part2_dijkstra),
+        ("Part 2 A*", run_part2_astar),
+        ("Part 2 Bidirectional", run_part2_bidirectional),
+    ],
 };
 
 fn run_part1(input: &str, b: Bench) -> BenchResult {
     let map = Map::parse(input);
-    b.bench(|| Ok::<_, NoError>(path_search::<false>(&map)))
+    let map = map.tiled(Expansion::NONE);
+    b.bench(|| Ok::<_, NoError>(path_search(&map, Algorithm::Dial)))
 }
 
 fn run_part2(input: &str, b: Bench) -> BenchResult {
+    run_part2_with(input, b, Algorithm::Dial)
+}
+
+fn run_part2_dijkstra(input: &str, b: Bench) -> BenchResult {
+    run_part2_with(input, b, Algorithm::Dijkstra)
+}
+
+fn run_part2_astar(input: &str, b: Bench) -> BenchResult {
+    run_part2_with(input, b, Algorithm::AStar)
+}
+
+fn run_part2_bidirectional(input: &str, b: Bench) -> BenchResult {
+    run_part2_with(input, b, Algorithm::Bidirectional)
+}
+
+fn run_part2_with(input: &str, b: Bench, algorithm: Algorithm) -> BenchResult {
+    let map = Map::parse(input);
+    let map = map.tiled(Expansion::AOC);
+    b.bench(|| Ok::<_, NoError>(path_search(&map, algorithm)))
+}
+
+fn run_part1_route(input: &str, b: Bench) -> BenchResult {
     let map = Map::parse(input);
-    b.bench(|| Ok::<_, NoError>(path_search::<true>(&map)))
+    let map = map.tiled(Expansion::NONE);
+    b.bench_alt(|| {
+        let route = find_route(&map, Algorithm::Dial).ok_or("path not found")?;
+        Ok::<_, &str>(render_route_ansi(&map, &route))
+    })
 }
 
 fn run_parse_part1(input: &str, b: Bench) -> BenchResult {
@@ -33,8 +71,6 @@ struct Map {
     tiles: Vec<u8>,
     width: usize,
     height: usize,
-    p2_width: usize,
-    p2_height: usize,
 }
 
 impl Map {
@@ -55,137 +91,260 @@ impl Map {
             tiles,
             width,
             height,
-            p2_height: height * 5,
-            p2_width: width * 5,
         }
     }
 
-    fn contains<const ISP2: bool>(&self, point: Point) -> bool {
-        let (x_range, y_range) = if ISP2 {
-            (0..self.p2_width as i16, 0..self.p2_height as i16)
-        } else {
-            (0..self.width as i16, 0..self.height as i16)
-        };
+    fn tiled(&self, expansion: Expansion) -> TiledMap<'_> {
+        TiledMap {
+            map: self,
+            expansion,
+            width: self.width * expansion.repeat_x,
+            height: self.height * expansion.repeat_y,
+        }
+    }
+}
+
+/// How the map repeats to make the full cave.
+#[derive(Debug, Clone, Copy)]
+struct Expansion {
+    repeat_x: usize,
+    repeat_y: usize,
+    /// How much each tile's risk goes up by, given its column and row of tiles.
+    increment: fn(usize, usize) -> u64,
+    /// Risk levels wrap around from this back to 1.
+    wrap: u64,
+}
+
+impl Expansion {
+    const NONE: Expansion = Expansion {
+        repeat_x: 1,
+        repeat_y: 1,
+        increment: |_, _| 0,
+        wrap: 9,
+    };
+
+    const AOC: Expansion = Expansion {
+        repeat_x: 5,
+        repeat_y: 5,
+        increment: |x_tile, y_tile| (x_tile + y_tile) as u64,
+        wrap: 9,
+    };
+}
 
-        x_range.contains(&point.x) && y_range.contains(&point.y)
+/// The map repeated out into the full cave.
+#[derive(Debug, Clone, Copy)]
+struct TiledMap<'a> {
+    map: &'a Map,
+    expansion: Expansion,
+    width: usize,
+    height: usize,
+}
+
+impl TiledMap<'_> {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
     }
 
-    fn get_cost<const ISP2: bool>(&self, point: Point) -> u16 {
-        if !ISP2 {
-            let idx = point.y as usize * self.width + point.x as usize;
-            self.tiles[idx] as u16
-        } else {
-            let py = point.y as usize;
-            let px = point.x as usize;
-            let (real_y, y_tile) = (py % self.height, py / self.height);
-            let (real_x, x_tile) = (px % self.height, px / self.height);
+    fn get_cost(&self, point: Point) -> u64 {
+        let Map { width, height, .. } = *self.map;
+        let (px, py) = (point.x as usize, point.y as usize);
+        let (real_x, x_tile) = (px % width, px / width);
+        let (real_y, y_tile) = (py % height, py / height);
 
-            let hazard = self.tiles[real_y * self.width + real_x] as u16;
+        let hazard = self.map.tiles[real_y * width + real_x] as u64;
+        let increment = (self.expansion.increment)(x_tile, y_tile);
 
-            ((hazard - 1) + y_tile as u16 + x_tile as u16) % 9 + 1
-        }
+        (hazard - 1 + increment) % self.expansion.wrap + 1
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct Point {
-    x: i16,
-    y: i16,
-}
+impl Grid for TiledMap<'_> {
+    fn width(&self) -> usize {
+        self.width
+    }
 
-impl Point {
-    fn new(x: usize, y: usize) -> Self {
-        Self {
-            x: x as i16,
-            y: y as i16,
-        }
+    fn height(&self) -> usize {
+        self.height
     }
 
-    fn neighbours(self) -> [Point; 4] {
-        let Point { x, y } = self;
-        [
-            Point { x, y: y - 1 },
-            Point { x: x - 1, y },
-            Point { x: x + 1, y },
-            Point { x, y: y + 1 },
-        ]
+    fn step_cost(&self, _: Point, to: Point) -> Option<u64> {
+        Some(self.get_cost(to))
     }
 
-    fn to_idx(self, width: usize) -> usize {
-        self.y as usize * width + self.x as usize
+    fn min_step_cost(&self) -> u64 {
+        1
     }
 
-    fn estimate_cost(self, target: Self) -> u16 {
-        ((target.x - self.x).abs() + (target.y - self.y).abs()) as u16
+    fn max_step_cost(&self) -> u64 {
+        self.expansion.wrap
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq)]
-struct State {
-    heuristic_cost: u16,
-    cost: u16,
-    pos: Point,
+/// Points and moves a search isn't allowed to use.
+#[derive(Debug, Clone, Default)]
+struct Blocked {
+    points: HashSet<Point>,
+    moves: HashSet<(Point, Point)>,
 }
 
-impl PartialEq for State {
-    fn eq(&self, other: &Self) -> bool {
-        self.heuristic_cost == other.heuristic_cost
-    }
+struct BlockedMap<'a> {
+    map: &'a TiledMap<'a>,
+    blocked: &'a Blocked,
 }
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.heuristic_cost.cmp(&self.heuristic_cost)
+impl Grid for BlockedMap<'_> {
+    fn width(&self) -> usize {
+        self.map.width
     }
-}
 
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    fn height(&self) -> usize {
+        self.map.height
     }
-}
 
-fn path_search<const ISP2: bool>(map: &Map) -> u16 {
-    let origin = Point::new(0, 0);
-    let mut queue = BinaryHeap::new();
+    fn step_cost(&self, from: Point, to: Point) -> Option<u64> {
+        if self.blocked.points.contains(&to) || self.blocked.moves.contains(&(from, to)) {
+            return None;
+        }
+        self.map.step_cost(from, to)
+    }
 
-    let (width, height) = if ISP2 {
-        (map.p2_width, map.p2_height)
-    } else {
-        (map.width, map.height)
-    };
+    fn min_step_cost(&self) -> u64 {
+        self.map.min_step_cost()
+    }
 
+    fn max_step_cost(&self) -> u64 {
+        self.map.max_step_cost()
+    }
+}
+
+fn find_route(map: &TiledMap, algorithm: Algorithm) -> Option<Path> {
+    let (width, height) = map.size();
     let target = Point::new(width - 1, height - 1);
-    let mut dist = vec![u16::MAX; width * height];
-
-    dist[origin.to_idx(width)] = 0;
-    queue.push(State {
-        cost: 0,
-        heuristic_cost: 0,
-        pos: origin,
-    });
-
-    while let Some(next) = queue.pop() {
-        for neighbour in next.pos.neighbours() {
-            if !map.contains::<ISP2>(neighbour) {
-                continue;
+    shortest_path(
+        map,
+        &[Point::new(0, 0)],
+        &[target],
+        SearchConfig::new(algorithm),
+    )
+}
+
+fn path_search(map: &TiledMap, algorithm: Algorithm) -> u64 {
+    find_route(map, algorithm).expect("path not found").cost
+}
+
+/// Finds the `k` cheapest paths which don't revisit any point, cheapest first, using
+/// Yen's algorithm.
+#[allow(unused)]
+fn k_cheapest_routes(map: &TiledMap, k: usize) -> Vec<Path> {
+    let mut routes = Vec::new();
+    let mut candidates = BinaryHeap::new();
+    let mut seen = HashSet::new();
+
+    match find_route(map, Algorithm::AStar) {
+        Some(route) if k > 0 => {
+            seen.insert(route.points.clone());
+            routes.push(route);
+        }
+        _ => return routes,
+    }
+    let target = routes[0].points[routes[0].points.len() - 1];
+
+    while routes.len() < k {
+        let last = &routes[routes.len() - 1].points;
+
+        // Try leaving the last route at each point, without following any known route
+        // out of there or going back through the part we've kept.
+        for spur_idx in 0..last.len() - 1 {
+            let root = &last[..=spur_idx];
+            let mut blocked = Blocked::default();
+            blocked.points.extend(&root[..spur_idx]);
+            for route in &routes {
+                if route.points.starts_with(root) {
+                    blocked
+                        .moves
+                        .insert((route.points[spur_idx], route.points[spur_idx + 1]));
+                }
+            }
+
+            let blocked = BlockedMap {
+                map,
+                blocked: &blocked,
+            };
+            let config = SearchConfig::new(Algorithm::AStar);
+            let spur = match shortest_path(&blocked, &[last[spur_idx]], &[target], config) {
+                Some(spur) => spur,
+                None => continue,
+            };
+            let root_cost: u64 = root[1..].iter().map(|&p| map.get_cost(p)).sum();
+
+            let mut path = root[..spur_idx].to_vec();
+            path.extend(spur.points);
+            if seen.insert(path.clone()) {
+                let cost = root_cost + spur.cost;
+                candidates.push(std::cmp::Reverse((cost, path)));
             }
-            let total_cost = next.cost + map.get_cost::<ISP2>(neighbour);
-            if neighbour == target {
-                return total_cost;
+        }
+
+        match candidates.pop() {
+            Some(std::cmp::Reverse((cost, points))) => routes.push(Path { cost, points }),
+            None => break,
+        }
+    }
+
+    routes
+}
+
+/// How light to draw a risk, scaled so the riskiest the map can have is `darkest`.
+fn grey_level(map: &TiledMap, risk: u64, darkest: u8) -> u8 {
+    let max_risk = map.max_step_cost().max(1);
+    let shade = (255 - darkest as u64) * risk.min(max_risk) / max_risk;
+    (255 - shade) as u8
+}
+
+/// Draws the risk map with the route highlighted.
+fn render_route_ansi(map: &TiledMap, route: &Path) -> String {
+    let (width, height) = map.size();
+    let on_route: HashSet<_> = route.points.iter().copied().collect();
+    let mut output = format!("Total risk: {}\n", route.cost);
+
+    for y in 0..height {
+        for x in 0..width {
+            let point = Point::new(x, y);
+            let risk = map.get_cost(point);
+            // Writing to a String can't fail.
+            if on_route.contains(&point) {
+                write!(output, "\x1b[1;97;41m{}", risk).unwrap();
+            } else {
+                let level = grey_level(map, risk, 75);
+                write!(output, "\x1b[0;38;2;{0};{0};{0}m{1}", level, risk).unwrap();
             }
-            if total_cost < dist[neighbour.to_idx(width)] {
-                dist[neighbour.to_idx(width)] = total_cost;
-                queue.push(State {
-                    heuristic_cost: total_cost + neighbour.estimate_cost(target),
-                    cost: total_cost,
-                    pos: neighbour,
-                });
+        }
+        output.push_str("\x1b[0m\n");
+    }
+
+    output
+}
+
+/// Draws the risk map in greys, darker for riskier, with the route in red.
+#[allow(unused)]
+fn render_route_ppm(map: &TiledMap, route: &Path, scale: usize) -> Vec<u8> {
+    let (width, height) = map.size();
+    let on_route: HashSet<_> = route.points.iter().copied().collect();
+    let mut output = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+
+    for y in 0..height * scale {
+        for x in 0..width * scale {
+            let point = Point::new(x / scale, y / scale);
+            if on_route.contains(&point) {
+                output.extend([220, 30, 30]);
+            } else {
+                let level = grey_level(map, map.get_cost(point), 30);
+                output.extend([level, level, level]);
             }
         }
     }
 
-    panic!("path not found");
+    output
 }
 
 #[cfg(test)]
@@ -193,6 +352,13 @@ mod tests_template {
     use super::*;
     use aoc_lib::Example;
 
+    const ALGORITHMS: [Algorithm; 4] = [
+        Algorithm::Dijkstra,
+        Algorithm::AStar,
+        Algorithm::Dial,
+        Algorithm::Bidirectional,
+    ];
+
     #[test]
     fn part1_test() {
         let input = aoc_lib::input(15)
@@ -201,7 +367,10 @@ mod tests_template {
             .unwrap();
 
         let map = Map::parse(&input);
-        assert_eq!(40, path_search::<false>(&map));
+        let map = map.tiled(Expansion::NONE);
+        for algorithm in ALGORITHMS {
+            assert_eq!(40, path_search(&map, algorithm));
+        }
     }
 
     #[test]
@@ -212,6 +381,99 @@ mod tests_template {
             .unwrap();
 
         let map = Map::parse(&input);
-        assert_eq!(315, path_search::<true>(&map));
+        let map = map.tiled(Expansion::AOC);
+        for algorithm in ALGORITHMS {
+            assert_eq!(315, path_search(&map, algorithm));
+        }
+    }
+
+    #[test]
+    fn route_test() {
+        let input = aoc_lib::input(15)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let map = Map::parse(&input);
+        let map = map.tiled(Expansion::NONE);
+        let route = find_route(&map, Algorithm::AStar).unwrap();
+        assert_eq!(40, route.cost);
+        assert_eq!(Point::new(0, 0), route.points[0]);
+        assert_eq!(Point::new(9, 9), route.points[route.points.len() - 1]);
+        let risk: u64 = route.points[1..].iter().map(|&p| map.get_cost(p)).sum();
+        assert_eq!(40, risk);
+
+        let rendered = render_route_ansi(&map, &route);
+        assert_eq!(11, rendered.lines().count());
+        let ppm = render_route_ppm(&map, &route, 2);
+        assert_eq!(b"P6\n20 20\n255\n".len() + 20 * 20 * 3, ppm.len());
+        assert_eq!([220, 30, 30], ppm[ppm.len() - 3..]);
+    }
+
+    #[test]
+    fn k_cheapest_test() {
+        let input = aoc_lib::input(15)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let map = Map::parse(&input);
+        let map = map.tiled(Expansion::NONE);
+        let routes = k_cheapest_routes(&map, 5);
+        assert_eq!(5, routes.len());
+        assert_eq!(40, routes[0].cost);
+
+        let paths: HashSet<_> = routes.iter().map(|r| &r.points).collect();
+        assert_eq!(5, paths.len());
+        for pair in routes.windows(2) {
+            assert!(pair[0].cost <= pair[1].cost);
+        }
+        for route in &routes {
+            let risk: u64 = route.points[1..].iter().map(|&p| map.get_cost(p)).sum();
+            assert_eq!(route.cost, risk);
+            let points: HashSet<_> = route.points.iter().collect();
+            assert_eq!(route.points.len(), points.len());
+            for step in route.points.windows(2) {
+                assert_eq!(1, step[0].manhattan(step[1]));
+            }
+        }
+    }
+
+    #[test]
+    fn expansion_test() {
+        let map = Map::parse("123\n456");
+
+        // Wider than it is tall, so mixing up the two picks the wrong tiles.
+        let tiled = map.tiled(Expansion::AOC);
+        assert_eq!((15, 10), tiled.size());
+        let costs: Vec<_> = (0..15).map(|x| tiled.get_cost(Point::new(x, 3))).collect();
+        assert_eq!(vec![5, 6, 7, 6, 7, 8, 7, 8, 9, 8, 9, 1, 9, 1, 2], costs);
+
+        let expansion = Expansion {
+            repeat_x: 3,
+            repeat_y: 1,
+            increment: |x_tile, _| 2 * x_tile as u64,
+            wrap: 6,
+        };
+        let tiled = map.tiled(expansion);
+        assert_eq!((9, 2), tiled.size());
+        let costs: Vec<_> = (0..9).map(|x| tiled.get_cost(Point::new(x, 1))).collect();
+        assert_eq!(vec![4, 5, 6, 6, 1, 2, 2, 3, 4], costs);
+        assert_eq!(24, path_search(&tiled, Algorithm::Dial));
+
+        // Risks well past 9, and past what fits in a byte, still draw in range.
+        let expansion = Expansion {
+            repeat_x: 4,
+            repeat_y: 1,
+            increment: |x_tile, _| 100 * x_tile as u64,
+            wrap: 1000,
+        };
+        let tiled = map.tiled(expansion);
+        let route = find_route(&tiled, Algorithm::Dial).unwrap();
+        assert_eq!(255 - 225 * 309 / 1000, grey_level(&tiled, 309, 30) as u64);
+        assert_eq!(30, grey_level(&tiled, 1000, 30));
+        assert_eq!(3, render_route_ansi(&tiled, &route).lines().count());
+        let ppm = render_route_ppm(&tiled, &route, 1);
+        assert_eq!(b"P6\n12 2\n255\n".len() + 12 * 2 * 3, ppm.len());
     }
 }
@@ -0,0 +1,363 @@
+use std::ops::RangeInclusive;
+
+use aoc_lib::{Bench, BenchResult, Day, ParseResult, UserError};
+use color_eyre::{
+    eyre::{eyre, Context, Result},
+    Report,
+};
+
+pub const DAY: Day = Day {
+    day: 17,
+    name: "Trick Shot",
+    part_1: run_part1,
+    part_2: Some(run_part2),
+    other: &[("Parse", run_parse)],
+};
+
+fn run_part1(input: &str, b: Bench) -> BenchResult {
+    let target = Target::parse(input).map_err(UserError)?;
+    b.bench(|| part1(&target))
+}
+
+fn run_part2(input: &str, b: Bench) -> BenchResult {
+    let target = Target::parse(input).map_err(UserError)?;
+    b.bench(|| part2(&target))
+}
+
+fn run_parse(input: &str, b: Bench) -> BenchResult {
+    b.bench(|| {
+        let target = Target::parse(input)?;
+        Ok::<_, Report>(ParseResult(target))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Target {
+    x: RangeInclusive<i64>,
+    y: RangeInclusive<i64>,
+}
+
+impl Target {
+    fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        let ranges = input
+            .strip_prefix("target area: ")
+            .ok_or_else(|| eyre!("Invalid target: {}", input))?;
+        let (x, y) = ranges
+            .split_once(", ")
+            .ok_or_else(|| eyre!("Invalid target: {}", input))?;
+
+        Ok(Self {
+            x: parse_range(x, "x=")?,
+            y: parse_range(y, "y=")?,
+        })
+    }
+}
+
+/// Bounds are read as `i32` and widened, which leaves room in `i64` for the heights
+/// and distances the probe covers on its way to any target that parses.
+fn parse_range(range: &str, prefix: &str) -> Result<RangeInclusive<i64>> {
+    let (start, end) = range
+        .strip_prefix(prefix)
+        .and_then(|r| r.split_once(".."))
+        .ok_or_else(|| eyre!("Invalid range: {}", range))?;
+
+    let start: i32 = start
+        .parse()
+        .with_context(|| eyre!("Invalid range: {}", range))?;
+    let end: i32 = end
+        .parse()
+        .with_context(|| eyre!("Invalid range: {}", range))?;
+
+    let (start, end) = (i64::from(start), i64::from(end));
+    Ok(start.min(end)..=start.max(end))
+}
+
+fn triangular(n: i64) -> i64 {
+    n * (n + 1) / 2
+}
+
+/// The slowest speed which covers at least `dist` before drag stops it.
+fn min_speed_covering(dist: i64) -> i64 {
+    let mut speed = ((2.0 * dist as f64).sqrt() as i64).max(1) - 1;
+    while triangular(speed) < dist {
+        speed += 1;
+    }
+
+    speed
+}
+
+/// The steps during which the probe is over the target on one axis. `last` is `None`
+/// if it stops there and never leaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StepWindow {
+    first: u64,
+    last: Option<u64>,
+}
+
+impl StepWindow {
+    fn overlaps(self, other: StepWindow) -> bool {
+        let before = |a: StepWindow, b: StepWindow| matches!(a.last, Some(last) if last < b.first);
+        !before(self, other) && !before(other, self)
+    }
+}
+
+/// The horizontal velocities which could possibly reach the target. Drag stops the
+/// probe after covering a triangular number, so anything slower falls short, and
+/// anything faster than the far edge overshoots on the first step.
+fn x_velocities(target: &Target) -> RangeInclusive<i64> {
+    let (&x_min, &x_max) = (target.x.start(), target.x.end());
+    let slowest = if x_min > 0 {
+        min_speed_covering(x_min)
+    } else {
+        x_min
+    };
+    let fastest = if x_max < 0 {
+        -min_speed_covering(-x_max)
+    } else {
+        x_max
+    };
+
+    slowest..=fastest
+}
+
+fn x_window(vx: i64, target: &Target) -> Option<StepWindow> {
+    let (mut x, mut v) = (0, vx);
+    let mut window: Option<StepWindow> = None;
+
+    for step in 1_u64.. {
+        x += v;
+        v -= v.signum();
+
+        if target.x.contains(&x) {
+            let first = window.map_or(step, |w| w.first);
+            window = Some(StepWindow {
+                first,
+                last: Some(step),
+            });
+            if v == 0 {
+                return Some(StepWindow { first, last: None });
+            }
+        }
+
+        let passed = (v >= 0 && x > *target.x.end()) || (v <= 0 && x < *target.x.start());
+        if v == 0 || passed {
+            break;
+        }
+    }
+
+    window
+}
+
+/// The vertical velocities which could possibly reach the target. A probe thrown
+/// upwards comes back through zero going one faster than it was thrown, so it has to
+/// be slow enough for that step not to jump past the target.
+fn y_velocities(target: &Target) -> RangeInclusive<i64> {
+    let (&y_min, &y_max) = (target.y.start(), target.y.end());
+    let slowest = if y_min > 0 {
+        min_speed_covering(y_min)
+    } else {
+        y_min
+    };
+    let fastest = y_max.max(-y_min - 1);
+
+    slowest..=fastest
+}
+
+/// The steps during which the probe is level with the target. A target above the
+/// launcher can be crossed twice, once on the way up and once on the way down, so each
+/// crossing gets its own window.
+fn y_windows(vy: i64, target: &Target) -> Vec<StepWindow> {
+    // The way down retraces the way up, so for a target below the launcher skip
+    // straight to the step where the probe falls back through zero.
+    let (mut step, mut y, mut v) = if vy > 0 && *target.y.end() < 0 {
+        (2 * vy as u64 + 1, 0, -vy - 1)
+    } else {
+        (0, 0, vy)
+    };
+    let mut windows: Vec<StepWindow> = Vec::new();
+
+    loop {
+        step += 1;
+        y += v;
+        v -= 1;
+
+        if target.y.contains(&y) {
+            match windows.last_mut() {
+                Some(StepWindow {
+                    last: Some(last), ..
+                }) if *last == step - 1 => *last = step,
+                _ => windows.push(StepWindow {
+                    first: step,
+                    last: Some(step),
+                }),
+            }
+        }
+        if v < 0 && y < *target.y.start() {
+            break;
+        }
+    }
+
+    windows
+}
+
+/// Every initial velocity which puts the probe over the target after some step.
+///
+/// A probe thrown up at `vy` is back at zero after `2 * vy + 1` steps, however fast it
+/// was thrown. So if the target is level with the launcher and the probe can stop
+/// over it, every fast enough upward throw hits, which is reported as an error.
+fn valid_velocities(target: &Target) -> Result<impl Iterator<Item = (i64, i64)> + '_> {
+    let x_windows: Vec<_> = x_velocities(target)
+        .filter_map(|vx| Some((vx, x_window(vx, target)?)))
+        .collect();
+
+    let mut vys = y_velocities(target);
+    if target.y.contains(&0) {
+        let mut lasts = x_windows.iter().map(|(_, w)| w.last);
+        let latest = lasts
+            .try_fold(0, |latest, last| Some(latest.max(last?)))
+            .ok_or_else(|| eyre!("Infinitely many velocities hit the target"))?;
+        vys = *vys.start()..=(*vys.end()).max(latest as i64 / 2);
+    }
+
+    Ok(vys.flat_map(move |vy| {
+        let y_windows = y_windows(vy, target);
+        let hits: Vec<_> = (x_windows.iter())
+            .filter(|(_, x_window)| y_windows.iter().any(|&w| x_window.overlaps(w)))
+            .map(|&(vx, _)| (vx, vy))
+            .collect();
+        hits
+    }))
+}
+
+fn part1(target: &Target) -> Result<i64> {
+    let highest = valid_velocities(target)?
+        .map(|(_, vy)| triangular(vy.max(0)))
+        .max()
+        .unwrap_or(0);
+    Ok(highest)
+}
+
+fn part2(target: &Target) -> Result<usize> {
+    Ok(valid_velocities(target)?.count())
+}
+
+#[cfg(test)]
+mod tests_template {
+    use super::*;
+    use aoc_lib::Example;
+
+    #[test]
+    fn part1_test() {
+        let input = aoc_lib::input(17)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let target = Target::parse(&input).unwrap();
+        assert_eq!(45, part1(&target).unwrap());
+    }
+
+    #[test]
+    fn part2_test() {
+        let input = aoc_lib::input(17)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let target = Target::parse(&input).unwrap();
+        assert_eq!(112, part2(&target).unwrap());
+    }
+
+    #[test]
+    fn windows_test() {
+        let target = Target::parse("target area: x=20..30, y=-10..-5").unwrap();
+
+        assert_eq!(6, min_speed_covering(20));
+        assert_eq!(6..=30, x_velocities(&target));
+        assert_eq!(-10..=9, y_velocities(&target));
+
+        // 6 stops at 21, 7 at 28.
+        let stops = StepWindow {
+            first: 5,
+            last: None,
+        };
+        assert_eq!(Some(stops), x_window(6, &target));
+        let passes = StepWindow {
+            first: 1,
+            last: Some(1),
+        };
+        assert_eq!(Some(passes), x_window(30, &target));
+        assert_eq!(None, x_window(16, &target));
+
+        // Thrown up at 9, it comes back through zero on step 19 going down at 10.
+        let falls = StepWindow {
+            first: 20,
+            last: Some(20),
+        };
+        assert_eq!(vec![falls], y_windows(9, &target));
+        assert_eq!(brute_force(&target), part2(&target).unwrap());
+
+        // Mirrored to the left, and lifted above the launcher.
+        let mirrored = Target::parse("target area: x=-30..-20, y=-10..-5").unwrap();
+        assert_eq!(112, part2(&mirrored).unwrap());
+        let above = Target::parse("target area: x=20..30, y=5..10").unwrap();
+        assert_eq!(brute_force(&above), part2(&above).unwrap());
+        assert_eq!(55, part1(&above).unwrap());
+
+        // Above the launcher it's crossed going up and again coming down, with a gap in
+        // between where the probe is over it horizontally but too high.
+        let above = Target::parse("target area: x=18..30, y=17..18").unwrap();
+        let windows = [4, 9].map(|step| StepWindow {
+            first: step,
+            last: Some(step),
+        });
+        assert_eq!(windows.to_vec(), y_windows(6, &above));
+        assert_eq!(48, brute_force(&above));
+        assert_eq!(48, part2(&above).unwrap());
+        assert!(valid_velocities(&above)
+            .unwrap()
+            .all(|v| v != (8, 9) && v != (9, 17)));
+    }
+
+    #[test]
+    fn level_target_test() {
+        // Level with the launcher and the probe can stop over it, so any fast enough
+        // upward throw comes back down onto it.
+        let level = Target::parse("target area: x=20..30, y=-5..5").unwrap();
+        assert!(part1(&level).is_err());
+        assert!(part2(&level).is_err());
+
+        // Here it always flies past, so only throws that come back in time count. Thrown
+        // up at 2 it's back at zero on step 5, its last one over the target at 6 across.
+        let level = Target::parse("target area: x=18..20, y=-1..0").unwrap();
+        assert_eq!(brute_force(&level), part2(&level).unwrap());
+        assert!(valid_velocities(&level).unwrap().any(|v| v == (6, 2)));
+    }
+
+    #[test]
+    fn deep_target_test() {
+        assert_eq!(63246, min_speed_covering(2_000_000_000));
+
+        let deep = Target::parse("target area: x=20..30, y=-100000..-99990").unwrap();
+        assert_eq!(triangular(99_999), part1(&deep).unwrap());
+        assert_eq!(4_999_950_000, triangular(99_999));
+    }
+
+    /// Counts the velocities which hit by trying every one that could, step by step.
+    fn brute_force(target: &Target) -> usize {
+        (-40_i64..=40)
+            .flat_map(|vx| (-40..=40).map(move |vy| (vx, vy)))
+            .filter(|&(vx, vy)| {
+                let (mut x, mut y, mut vx, mut vy) = (0, 0, vx, vy);
+                (0..200).any(|_| {
+                    x += vx;
+                    y += vy;
+                    vx -= vx.signum();
+                    vy -= 1;
+                    target.x.contains(&x) && target.y.contains(&y)
+                })
+            })
+            .count()
+    }
+}
@@ -0,0 +1,428 @@
+use std::{fmt::Display, ops::Add};
+
+use aoc_lib::{Bench, BenchResult, Day, NoError, ParseResult, UserError};
+use color_eyre::{
+    eyre::{eyre, Context, Result},
+    Report,
+};
+
+pub const DAY: Day = Day {
+    day: 18,
+    name: "Snailfish",
+    part_1: run_part1,
+    part_2: Some(run_part2),
+    other: &[("Parse", run_parse)],
+};
+
+fn run_part1(input: &str, b: Bench) -> BenchResult {
+    let numbers = parse(input).map_err(UserError)?;
+    b.bench(|| Ok::<_, NoError>(part1(&numbers)))
+}
+
+fn run_part2(input: &str, b: Bench) -> BenchResult {
+    let numbers = parse(input).map_err(UserError)?;
+    b.bench(|| Ok::<_, NoError>(part2(&numbers)))
+}
+
+fn run_parse(input: &str, b: Bench) -> BenchResult {
+    b.bench(|| {
+        let numbers = parse(input)?;
+        Ok::<_, Report>(ParseResult(numbers))
+    })
+}
+
+fn parse(input: &str) -> Result<Vec<Number>> {
+    let numbers: Vec<_> = (input.trim().lines().enumerate())
+        .map(|(line, text)| {
+            Number::parse(text.trim()).with_context(|| eyre!("Invalid number on line {}", line + 1))
+        })
+        .collect::<Result<_, _>>()?;
+
+    if numbers.is_empty() {
+        return Err(eyre!("No numbers found"));
+    }
+
+    Ok(numbers)
+}
+
+/// Pairs nested deeper than this explode.
+const MAX_DEPTH: u8 = 4;
+
+/// The largest regular number the parser accepts. Reducing takes time in proportion to
+/// how big the regular numbers are, so this is well below the point where they could
+/// overflow.
+const MAX_VALUE: u32 = 999_999;
+
+// Reducing never increases the total of a number's regular numbers, and a sum of two
+// has at most 32 of them, so none of them can get bigger than the total.
+const _: () = assert!(MAX_VALUE as u64 * (2 << MAX_DEPTH) <= u32::MAX as u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseErrorKind {
+    ExpectedOpen,
+    ExpectedComma,
+    ExpectedClose,
+    ExpectedValue,
+    ValueTooLarge,
+    /// Pairs can't be nested deeper than a reduced number allows.
+    TooDeep,
+    TrailingInput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ParseError {
+    /// The byte offset the error was found at.
+    position: usize,
+    kind: ParseErrorKind,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self.kind {
+            ParseErrorKind::ExpectedOpen => "expected `[`",
+            ParseErrorKind::ExpectedComma => "expected `,`",
+            ParseErrorKind::ExpectedClose => "expected `]`",
+            ParseErrorKind::ExpectedValue => "expected a number or `[`",
+            ParseErrorKind::ValueTooLarge => "number too large",
+            ParseErrorKind::TooDeep => "pairs nested too deeply",
+            ParseErrorKind::TrailingInput => "unexpected input after number",
+        };
+
+        write!(f, "{} at position {}", message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A regular number, tagged with how many pairs it's nested inside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Element {
+    value: u32,
+    depth: u8,
+}
+
+/// A snailfish number, stored as its regular numbers in order. The depths are enough
+/// to rebuild the pairs, and they make finding neighbours for explosions trivial.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Number {
+    elements: Vec<Element>,
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+    elements: Vec<Element>,
+}
+
+impl Parser<'_> {
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            position: self.position,
+            kind,
+        }
+    }
+
+    fn expect(&mut self, byte: u8, kind: ParseErrorKind) -> Result<(), ParseError> {
+        if self.input.get(self.position) != Some(&byte) {
+            return Err(self.error(kind));
+        }
+        self.position += 1;
+
+        Ok(())
+    }
+
+    fn pair(&mut self, depth: u8) -> Result<(), ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeep));
+        }
+
+        self.expect(b'[', ParseErrorKind::ExpectedOpen)?;
+        self.value(depth)?;
+        self.expect(b',', ParseErrorKind::ExpectedComma)?;
+        self.value(depth)?;
+        self.expect(b']', ParseErrorKind::ExpectedClose)
+    }
+
+    fn value(&mut self, depth: u8) -> Result<(), ParseError> {
+        match self.input.get(self.position) {
+            Some(b'[') => self.pair(depth + 1),
+            Some(b) if b.is_ascii_digit() => {
+                let start = self.position;
+                let mut value: u32 = 0;
+                while let Some(&b) = self.input.get(self.position).filter(|b| b.is_ascii_digit()) {
+                    value = (value.checked_mul(10))
+                        .and_then(|v| v.checked_add((b - b'0') as u32))
+                        .filter(|&v| v <= MAX_VALUE)
+                        .ok_or(ParseError {
+                            position: start,
+                            kind: ParseErrorKind::ValueTooLarge,
+                        })?;
+                    self.position += 1;
+                }
+
+                self.elements.push(Element { value, depth });
+                Ok(())
+            }
+            _ => Err(self.error(ParseErrorKind::ExpectedValue)),
+        }
+    }
+}
+
+impl Number {
+    fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            input: input.as_bytes(),
+            position: 0,
+            elements: Vec::new(),
+        };
+        parser.pair(1)?;
+        if parser.position != input.len() {
+            return Err(parser.error(ParseErrorKind::TrailingInput));
+        }
+
+        Ok(Self {
+            elements: parser.elements,
+        })
+    }
+
+    /// Explodes the leftmost pair nested too deeply, returning whether there was one.
+    fn explode(&mut self) -> bool {
+        let idx = match self.elements.iter().position(|e| e.depth > MAX_DEPTH) {
+            Some(idx) => idx,
+            None => return false,
+        };
+
+        // Anything that deep has to be a pair of regular numbers.
+        let Element { value: left, depth } = self.elements[idx];
+        let right = self.elements[idx + 1].value;
+        if let Some(prev) = idx.checked_sub(1) {
+            self.elements[prev].value += left;
+        }
+        if let Some(next) = self.elements.get_mut(idx + 2) {
+            next.value += right;
+        }
+
+        self.elements[idx] = Element {
+            value: 0,
+            depth: depth - 1,
+        };
+        self.elements.remove(idx + 1);
+
+        true
+    }
+
+    /// Splits the leftmost regular number of 10 or more, returning whether there was one.
+    fn split(&mut self) -> bool {
+        let idx = match self.elements.iter().position(|e| e.value >= 10) {
+            Some(idx) => idx,
+            None => return false,
+        };
+
+        let Element { value, depth } = self.elements[idx];
+        let left = Element {
+            value: value / 2,
+            depth: depth + 1,
+        };
+        let right = Element {
+            value: value - left.value,
+            depth: depth + 1,
+        };
+        self.elements[idx] = left;
+        self.elements.insert(idx + 1, right);
+
+        true
+    }
+
+    fn reduce(&mut self) {
+        loop {
+            while self.explode() {}
+            if !self.split() {
+                break;
+            }
+        }
+    }
+
+    fn magnitude(&self) -> u64 {
+        // Combine the pairs as they close, which is whenever the top two regular
+        // numbers are at the same depth.
+        let mut stack: Vec<(u64, u8)> = Vec::with_capacity(MAX_DEPTH as usize + 2);
+        for e in &self.elements {
+            let mut top = (e.value as u64, e.depth);
+            while let Some(&(left, depth)) = stack.last() {
+                if depth != top.1 {
+                    break;
+                }
+                stack.pop();
+                top = (3 * left + 2 * top.0, depth - 1);
+            }
+            stack.push(top);
+        }
+
+        stack.first().map_or(0, |&(value, _)| value)
+    }
+
+    fn write_value(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        idx: &mut usize,
+        depth: u8,
+    ) -> std::fmt::Result {
+        let element = self.elements[*idx];
+        if element.depth == depth {
+            *idx += 1;
+            return write!(f, "{}", element.value);
+        }
+
+        f.write_str("[")?;
+        self.write_value(f, idx, depth + 1)?;
+        f.write_str(",")?;
+        self.write_value(f, idx, depth + 1)?;
+        f.write_str("]")
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_value(f, &mut 0, 0)
+    }
+}
+
+impl Add for &Number {
+    type Output = Number;
+
+    fn add(self, rhs: &Number) -> Number {
+        let elements = (self.elements.iter())
+            .chain(&rhs.elements)
+            .map(|e| Element {
+                depth: e.depth + 1,
+                ..*e
+            })
+            .collect();
+
+        let mut sum = Number { elements };
+        sum.reduce();
+        sum
+    }
+}
+
+impl Add for Number {
+    type Output = Number;
+
+    fn add(self, rhs: Number) -> Number {
+        &self + &rhs
+    }
+}
+
+fn part1(numbers: &[Number]) -> u64 {
+    let sum = (numbers.iter().cloned()).reduce(|acc, n| acc + n);
+    sum.map_or(0, |sum| sum.magnitude())
+}
+
+/// Snailfish addition isn't commutative, so both orders of each pair are tried.
+fn part2(numbers: &[Number]) -> u64 {
+    let mut max = 0;
+    for (i, a) in numbers.iter().enumerate() {
+        for (j, b) in numbers.iter().enumerate() {
+            if i != j {
+                max = max.max((a + b).magnitude());
+            }
+        }
+    }
+
+    max
+}
+
+#[cfg(test)]
+mod tests_template {
+    use super::*;
+    use aoc_lib::Example;
+
+    #[test]
+    fn part1_test() {
+        let input = aoc_lib::input(18)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let numbers = parse(&input).unwrap();
+        assert_eq!(4140, part1(&numbers));
+
+        let sum = numbers.into_iter().reduce(|acc, n| acc + n).unwrap();
+        let expected = "[[[[6,6],[7,6]],[[7,7],[7,0]]],[[[7,7],[7,7]],[[7,8],[9,9]]]]";
+        assert_eq!(expected, sum.to_string());
+    }
+
+    #[test]
+    fn part1_test2() {
+        let input = aoc_lib::input(18)
+            .example(Example::Part1, 2)
+            .open()
+            .unwrap();
+
+        let numbers = parse(&input).unwrap();
+        let sum = numbers.into_iter().reduce(|acc, n| acc + n).unwrap();
+        assert_eq!("[[[[5,0],[7,4]],[5,5]],[6,6]]", sum.to_string());
+        assert_eq!(1137, sum.magnitude());
+    }
+
+    #[test]
+    fn part2_test() {
+        let input = aoc_lib::input(18)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+
+        let numbers = parse(&input).unwrap();
+        assert_eq!(3993, part2(&numbers));
+    }
+
+    #[test]
+    fn reduce_test() {
+        let a = Number::parse("[[[[4,3],4],4],[7,[[8,4],9]]]").unwrap();
+        let b = Number::parse("[1,1]").unwrap();
+        assert_eq!("[[[[0,7],4],[[7,8],[6,0]]],[8,1]]", (a + b).to_string());
+
+        let magnitude = Number::parse("[[1,2],[[3,4],5]]").unwrap().magnitude();
+        assert_eq!(143, magnitude);
+
+        // As big as the parser allows, next to a pair which explodes straight away.
+        let big = format!("[[[[1,{0}],{0}],1],1]", MAX_VALUE);
+        let sum = Number::parse(&big).unwrap() + Number::parse("[1,1]").unwrap();
+        let reduced = |e: &Element| e.value < 10 && e.depth <= MAX_DEPTH;
+        assert!(sum.elements.iter().all(reduced));
+    }
+
+    #[test]
+    fn parse_test() {
+        let input = aoc_lib::input(18)
+            .example(Example::Part1, 1)
+            .open()
+            .unwrap();
+        for line in input.lines() {
+            assert_eq!(line, Number::parse(line).unwrap().to_string());
+        }
+
+        let err = |input| Number::parse(input).unwrap_err();
+        let error = |position, kind| ParseError { position, kind };
+        assert_eq!(error(0, ParseErrorKind::ExpectedOpen), err("1"));
+        assert_eq!(error(2, ParseErrorKind::ExpectedComma), err("[1]"));
+        assert_eq!(error(4, ParseErrorKind::ExpectedClose), err("[1,2,3]"));
+        assert_eq!(error(3, ParseErrorKind::ExpectedValue), err("[1,x]"));
+        assert_eq!(error(5, ParseErrorKind::TrailingInput), err("[1,2]]"));
+        assert_eq!(
+            error(4, ParseErrorKind::TooDeep),
+            err("[[[[[1,2],3],4],5],6]")
+        );
+        assert_eq!(
+            error(1, ParseErrorKind::ValueTooLarge),
+            err("[99999999999,1]")
+        );
+        assert_eq!(
+            error(6, ParseErrorKind::ValueTooLarge),
+            err("[[[[1,4000000000],4000000000],1],1]")
+        );
+        let too_large = format!("[{},1]", MAX_VALUE + 1);
+        assert_eq!(error(1, ParseErrorKind::ValueTooLarge), err(&too_large));
+        assert_eq!("expected `,` at position 2", err("[1]").to_string());
+    }
+}
@@ -2,6 +2,7 @@ use aoc_lib::TracingAlloc;
 use color_eyre::Result;
 
 mod days;
+mod search;
 
 #[global_allocator]
 static ALLOC: TracingAlloc = TracingAlloc;
@@ -0,0 +1,628 @@
+//! Shortest path searches over grids, shared between the days that need them.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    /// Panics if either coordinate doesn't fit in an `i32`.
+    pub fn new(x: usize, y: usize) -> Self {
+        Self {
+            x: i32::try_from(x).expect("x coordinate too large for a point"),
+            y: i32::try_from(y).expect("y coordinate too large for a point"),
+        }
+    }
+
+    pub fn to_idx(self, width: usize) -> usize {
+        self.y as usize * width + self.x as usize
+    }
+
+    pub fn manhattan(self, other: Self) -> u64 {
+        self.x.abs_diff(other.x) as u64 + self.y.abs_diff(other.y) as u64
+    }
+
+    pub fn chebyshev(self, other: Self) -> u64 {
+        self.x.abs_diff(other.x).max(self.y.abs_diff(other.y)) as u64
+    }
+}
+
+pub trait Grid {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+
+    /// The cost of stepping from one cell onto a neighbouring one, or `None` if the step
+    /// isn't allowed. Both cells are inside the grid.
+    fn step_cost(&self, from: Point, to: Point) -> Option<u64>;
+
+    /// A lower bound on the cost of any step, which A* uses to estimate distances.
+    fn min_step_cost(&self) -> u64 {
+        0
+    }
+
+    /// An upper bound on the cost of any step, which sizes Dial's bucket queue. Dial
+    /// panics if a step costs more.
+    fn max_step_cost(&self) -> u64;
+
+    fn contains(&self, point: Point) -> bool {
+        let inside = |coord: i32, len: usize| usize::try_from(coord).is_ok_and(|c| c < len);
+        inside(point.x, self.width()) && inside(point.y, self.height())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Dijkstra,
+    AStar,
+    /// Dijkstra with a bucket queue, for small integer step costs. Grids whose steps
+    /// can cost more than `MAX_BUCKETS` use a binary heap instead.
+    Dial,
+    /// Dijkstra from both ends at once, until the two searches meet.
+    Bidirectional,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(unused)]
+pub enum Moves {
+    Four,
+    /// Diagonal steps cost `diagonal_cost` on top of what the grid charges.
+    Eight {
+        diagonal_cost: u64,
+    },
+}
+
+impl Moves {
+    const ORTHOGONAL: [(i32, i32); 4] = [(0, -1), (-1, 0), (1, 0), (0, 1)];
+    const DIAGONAL: [(i32, i32); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+    /// Each neighbour of the point, along with any extra cost of stepping onto it.
+    fn neighbours(self, point: Point) -> impl Iterator<Item = (Point, u64)> {
+        let diagonals: &[_] = match self {
+            Moves::Four => &[],
+            Moves::Eight { .. } => &Self::DIAGONAL,
+        };
+        let diagonal_cost = match self {
+            Moves::Four => 0,
+            Moves::Eight { diagonal_cost } => diagonal_cost,
+        };
+
+        let orthogonal = Self::ORTHOGONAL.iter().map(|&d| (d, 0));
+        let diagonal = diagonals.iter().map(move |&d| (d, diagonal_cost));
+        orthogonal.chain(diagonal).map(move |((dx, dy), extra)| {
+            let neighbour = Point {
+                x: point.x + dx,
+                y: point.y + dy,
+            };
+            (neighbour, extra)
+        })
+    }
+
+    fn max_extra(self) -> u64 {
+        match self {
+            Moves::Four => 0,
+            Moves::Eight { diagonal_cost } => diagonal_cost,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchConfig {
+    pub algorithm: Algorithm,
+    pub moves: Moves,
+}
+
+impl SearchConfig {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Self {
+            algorithm,
+            moves: Moves::Four,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    pub cost: u64,
+    /// Every point visited, from one of the starts to one of the goals.
+    pub points: Vec<Point>,
+}
+
+/// Finds the cheapest path from any of the starts to any of the goals.
+pub fn shortest_path<G: Grid>(
+    grid: &G,
+    starts: &[Point],
+    goals: &[Point],
+    config: SearchConfig,
+) -> Option<Path> {
+    let starts: Vec<_> = starts
+        .iter()
+        .copied()
+        .filter(|&p| grid.contains(p))
+        .collect();
+    let goals: Vec<_> = goals
+        .iter()
+        .copied()
+        .filter(|&p| grid.contains(p))
+        .collect();
+    if starts.is_empty() || goals.is_empty() {
+        return None;
+    }
+
+    match config.algorithm {
+        Algorithm::Dijkstra => best_first(
+            grid,
+            &starts,
+            &goals,
+            config.moves,
+            |_| 0,
+            BinaryQueue::default(),
+        ),
+        Algorithm::AStar => {
+            let min_cost = grid.min_step_cost();
+            let estimate = |p: Point| {
+                let dist = |&g: &Point| match config.moves {
+                    Moves::Four => p.manhattan(g),
+                    Moves::Eight { .. } => p.chebyshev(g),
+                };
+                goals.iter().map(dist).min().unwrap_or(0) * min_cost
+            };
+            best_first(
+                grid,
+                &starts,
+                &goals,
+                config.moves,
+                estimate,
+                BinaryQueue::default(),
+            )
+        }
+        Algorithm::Dial => {
+            let max_cost = (grid.max_step_cost()).saturating_add(config.moves.max_extra());
+            match usize::try_from(max_cost) {
+                Ok(max_cost) if max_cost < MAX_BUCKETS => {
+                    let queue = BucketQueue::new(max_cost);
+                    best_first(grid, &starts, &goals, config.moves, |_| 0, queue)
+                }
+                _ => {
+                    let queue = BinaryQueue::default();
+                    best_first(grid, &starts, &goals, config.moves, |_| 0, queue)
+                }
+            }
+        }
+        Algorithm::Bidirectional => bidirectional(grid, &starts, &goals, config.moves),
+    }
+}
+
+/// A queue of grid indices ordered by priority, smallest first.
+trait Queue {
+    fn push(&mut self, priority: u64, idx: usize);
+    fn pop(&mut self) -> Option<(u64, usize)>;
+}
+
+#[derive(Default)]
+struct BinaryQueue(BinaryHeap<Reverse<(u64, usize)>>);
+
+impl Queue for BinaryQueue {
+    fn push(&mut self, priority: u64, idx: usize) {
+        self.0.push(Reverse((priority, idx)));
+    }
+
+    fn pop(&mut self) -> Option<(u64, usize)> {
+        self.0.pop().map(|Reverse(entry)| entry)
+    }
+}
+
+/// The most buckets Dial's ring is allowed, past which the step costs are too spread
+/// out for buckets to be worth it.
+const MAX_BUCKETS: usize = 1 << 16;
+
+/// Dial's bucket queue. Priorities are never more than the largest step cost ahead of
+/// the last one popped, so a ring of buckets covers every priority in the queue.
+/// Anything further ahead would land in a bucket that's already been passed, so it's
+/// checked rather than trusted.
+struct BucketQueue {
+    buckets: Vec<Vec<usize>>,
+    current: u64,
+    len: usize,
+}
+
+impl BucketQueue {
+    fn new(max_step_cost: usize) -> Self {
+        Self {
+            buckets: vec![Vec::new(); max_step_cost + 1],
+            current: 0,
+            len: 0,
+        }
+    }
+}
+
+impl Queue for BucketQueue {
+    fn push(&mut self, priority: u64, idx: usize) {
+        let num_buckets = self.buckets.len() as u64;
+        assert!(
+            priority - self.current < num_buckets,
+            "step cost more than the grid's max_step_cost"
+        );
+        self.buckets[(priority % num_buckets) as usize].push(idx);
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<(u64, usize)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let num_buckets = self.buckets.len() as u64;
+        loop {
+            if let Some(idx) = self.buckets[(self.current % num_buckets) as usize].pop() {
+                self.len -= 1;
+                return Some((self.current, idx));
+            }
+            self.current += 1;
+        }
+    }
+}
+
+fn trace_path(prev: &[Option<Point>], end: Point, width: usize) -> Vec<Point> {
+    let mut points = vec![end];
+    while let Some(p) = prev[points[points.len() - 1].to_idx(width)] {
+        points.push(p);
+    }
+    points.reverse();
+
+    points
+}
+
+/// Dijkstra, or A* with a non-zero estimate, over whichever queue is given.
+fn best_first<G: Grid>(
+    grid: &G,
+    starts: &[Point],
+    goals: &[Point],
+    moves: Moves,
+    estimate: impl Fn(Point) -> u64,
+    mut queue: impl Queue,
+) -> Option<Path> {
+    let width = grid.width();
+    let mut dist = vec![u64::MAX; width * grid.height()];
+    let mut prev = vec![None; dist.len()];
+    let mut is_goal = vec![false; dist.len()];
+    for goal in goals {
+        is_goal[goal.to_idx(width)] = true;
+    }
+
+    for &start in starts {
+        dist[start.to_idx(width)] = 0;
+        queue.push(estimate(start), start.to_idx(width));
+    }
+
+    while let Some((priority, idx)) = queue.pop() {
+        let pos = Point::new(idx % width, idx / width);
+        let cost = dist[idx];
+        // A stale entry, from before we found a cheaper way here.
+        if priority != cost + estimate(pos) {
+            continue;
+        }
+        if is_goal[idx] {
+            let points = trace_path(&prev, pos, width);
+            return Some(Path { cost, points });
+        }
+
+        for (neighbour, extra) in moves.neighbours(pos) {
+            if !grid.contains(neighbour) {
+                continue;
+            }
+            let step = match grid.step_cost(pos, neighbour) {
+                Some(step) => step + extra,
+                None => continue,
+            };
+
+            let n_idx = neighbour.to_idx(width);
+            let total_cost = cost + step;
+            if total_cost < dist[n_idx] {
+                dist[n_idx] = total_cost;
+                prev[n_idx] = Some(pos);
+                queue.push(total_cost + estimate(neighbour), n_idx);
+            }
+        }
+    }
+
+    None
+}
+
+/// One half of a bidirectional search.
+struct Frontier {
+    dist: Vec<u64>,
+    prev: Vec<Option<Point>>,
+    queue: BinaryQueue,
+}
+
+impl Frontier {
+    fn new(len: usize, sources: &[Point], width: usize) -> Self {
+        let mut frontier = Self {
+            dist: vec![u64::MAX; len],
+            prev: vec![None; len],
+            queue: BinaryQueue::default(),
+        };
+        for &source in sources {
+            frontier.dist[source.to_idx(width)] = 0;
+            frontier.queue.push(0, source.to_idx(width));
+        }
+
+        frontier
+    }
+
+    /// The smallest distance still queued, skipping past stale entries.
+    fn peek(&mut self) -> Option<u64> {
+        while let Some(&Reverse((cost, idx))) = self.queue.0.peek() {
+            if cost == self.dist[idx] {
+                return Some(cost);
+            }
+            self.queue.0.pop();
+        }
+
+        None
+    }
+}
+
+fn bidirectional<G: Grid>(
+    grid: &G,
+    starts: &[Point],
+    goals: &[Point],
+    moves: Moves,
+) -> Option<Path> {
+    let width = grid.width();
+    let len = width * grid.height();
+    let mut forward = Frontier::new(len, starts, width);
+    let mut backward = Frontier::new(len, goals, width);
+
+    // The cheapest path found so far, and where the two halves meet on it.
+    let mut best = u64::MAX;
+    let mut meeting = None;
+    for &start in starts {
+        if goals.contains(&start) {
+            best = 0;
+            meeting = Some(start);
+        }
+    }
+
+    while let (Some(f_top), Some(b_top)) = (forward.peek(), backward.peek()) {
+        if f_top.saturating_add(b_top) >= best {
+            break;
+        }
+
+        let is_forward = f_top <= b_top;
+        let (this, other) = if is_forward {
+            (&mut forward, &backward)
+        } else {
+            (&mut backward, &forward)
+        };
+
+        let (cost, idx) = this.queue.pop().unwrap();
+        let pos = Point::new(idx % width, idx / width);
+
+        for (neighbour, extra) in moves.neighbours(pos) {
+            if !grid.contains(neighbour) {
+                continue;
+            }
+            // The backward search follows steps in reverse.
+            let step = if is_forward {
+                grid.step_cost(pos, neighbour)
+            } else {
+                grid.step_cost(neighbour, pos)
+            };
+            let step = match step {
+                Some(step) => step + extra,
+                None => continue,
+            };
+
+            let n_idx = neighbour.to_idx(width);
+            let total_cost = cost + step;
+            if total_cost < this.dist[n_idx] {
+                this.dist[n_idx] = total_cost;
+                this.prev[n_idx] = Some(pos);
+                this.queue.push(total_cost, n_idx);
+
+                if other.dist[n_idx] != u64::MAX && total_cost + other.dist[n_idx] < best {
+                    best = total_cost + other.dist[n_idx];
+                    meeting = Some(neighbour);
+                }
+            }
+        }
+    }
+
+    let meeting = meeting?;
+    let mut points = trace_path(&forward.prev, meeting, width);
+    let mut back = trace_path(&backward.prev, meeting, width);
+    back.reverse();
+    points.extend(&back[1..]);
+
+    Some(Path { cost: best, points })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every cell costs its digit to step onto, and `#` is a wall.
+    struct Digits {
+        cells: Vec<Option<u64>>,
+        width: usize,
+    }
+
+    impl Digits {
+        fn parse(input: &str) -> Self {
+            let width = input.lines().next().unwrap().len();
+            let cells = input
+                .lines()
+                .flat_map(|l| l.chars().map(|c| c.to_digit(10).map(u64::from)))
+                .collect();
+
+            Self { cells, width }
+        }
+    }
+
+    impl Grid for Digits {
+        fn width(&self) -> usize {
+            self.width
+        }
+
+        fn height(&self) -> usize {
+            self.cells.len() / self.width
+        }
+
+        fn step_cost(&self, _: Point, to: Point) -> Option<u64> {
+            self.cells[to.to_idx(self.width)]
+        }
+
+        fn min_step_cost(&self) -> u64 {
+            1
+        }
+
+        fn max_step_cost(&self) -> u64 {
+            9
+        }
+    }
+
+    /// Claims steps cost less than they can.
+    struct Understated(Digits);
+
+    impl Grid for Understated {
+        fn width(&self) -> usize {
+            self.0.width()
+        }
+
+        fn height(&self) -> usize {
+            self.0.height()
+        }
+
+        fn step_cost(&self, from: Point, to: Point) -> Option<u64> {
+            self.0.step_cost(from, to)
+        }
+
+        fn max_step_cost(&self) -> u64 {
+            2
+        }
+    }
+
+    const ALGORITHMS: [Algorithm; 4] = [
+        Algorithm::Dijkstra,
+        Algorithm::AStar,
+        Algorithm::Dial,
+        Algorithm::Bidirectional,
+    ];
+
+    fn path_cost(grid: &Digits, path: &Path, diagonal_cost: u64) -> u64 {
+        (path.points.windows(2))
+            .map(|step| {
+                let diagonal = step[0].x != step[1].x && step[0].y != step[1].y;
+                let extra = if diagonal { diagonal_cost } else { 0 };
+                grid.step_cost(step[0], step[1]).unwrap() + extra
+            })
+            .sum()
+    }
+
+    #[test]
+    fn algorithms_agree_test() {
+        let grid = Digits::parse("1163751\n13#1381\n21365#3\n3694931\n7463417\n1319128\n1359912");
+        let start = [Point::new(0, 0)];
+        let goal = [Point::new(6, 6)];
+
+        for moves in [Moves::Four, Moves::Eight { diagonal_cost: 1 }] {
+            let costs: Vec<_> = ALGORITHMS
+                .iter()
+                .map(|&algorithm| {
+                    let config = SearchConfig { algorithm, moves };
+                    let path = shortest_path(&grid, &start, &goal, config).unwrap();
+                    assert_eq!(start[0], path.points[0]);
+                    assert_eq!(goal[0], path.points[path.points.len() - 1]);
+                    assert_eq!(path.cost, path_cost(&grid, &path, moves.max_extra()));
+                    path.cost
+                })
+                .collect();
+            assert!(costs.iter().all(|&c| c == costs[0]), "{:?}", costs);
+        }
+    }
+
+    /// Claims steps can cost far more than they do.
+    struct Overstated(Digits);
+
+    impl Grid for Overstated {
+        fn width(&self) -> usize {
+            self.0.width()
+        }
+
+        fn height(&self) -> usize {
+            self.0.height()
+        }
+
+        fn step_cost(&self, from: Point, to: Point) -> Option<u64> {
+            self.0.step_cost(from, to)
+        }
+
+        fn max_step_cost(&self) -> u64 {
+            u64::MAX
+        }
+    }
+
+    #[test]
+    fn dial_fallback_test() {
+        let grid = Digits::parse("1163751\n13#1381\n21365#3\n3694931\n7463417\n1319128\n1359912");
+        let (start, goal) = ([Point::new(0, 0)], [Point::new(6, 6)]);
+        let moves = Moves::Eight { diagonal_cost: 3 };
+        let config = |algorithm| SearchConfig { algorithm, moves };
+
+        let expected = shortest_path(&grid, &start, &goal, config(Algorithm::Dijkstra));
+        let overstated = Overstated(grid);
+        let path = shortest_path(&overstated, &start, &goal, config(Algorithm::Dial));
+        assert_eq!(expected.unwrap().cost, path.unwrap().cost);
+    }
+
+    #[test]
+    #[should_panic(expected = "too large")]
+    fn point_range_test() {
+        assert_eq!(7, Point::new(3, 4).manhattan(Point::new(0, 0)));
+        assert_eq!(4, Point::new(3, 4).chebyshev(Point::new(0, 0)));
+        let far = Point {
+            x: i32::MAX,
+            y: i32::MIN,
+        };
+        let opposite = Point { x: i32::MIN, y: 0 };
+        assert_eq!(u32::MAX as u64, far.chebyshev(opposite));
+        assert!(!Overstated(Digits::parse("1")).contains(far));
+
+        Point::new(i32::MAX as usize + 1, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "max_step_cost")]
+    fn dial_bound_test() {
+        let grid = Understated(Digits::parse("191\n111"));
+        let config = SearchConfig::new(Algorithm::Dial);
+        shortest_path(&grid, &[Point::new(0, 0)], &[Point::new(2, 0)], config);
+    }
+
+    #[test]
+    fn multiple_endpoints_test() {
+        let grid = Digits::parse("9119\n9#99\n1111");
+        let starts = [Point::new(0, 0), Point::new(0, 2)];
+        let goals = [Point::new(3, 0), Point::new(3, 2)];
+
+        for algorithm in ALGORITHMS {
+            let path = shortest_path(&grid, &starts, &goals, SearchConfig::new(algorithm));
+            let path = path.unwrap();
+            assert_eq!(3, path.cost, "{:?}", algorithm);
+            assert_eq!(Point::new(0, 2), path.points[0]);
+            assert_eq!(Point::new(3, 2), path.points[3]);
+        }
+
+        let walled = Digits::parse("1#1\n1#1");
+        let start = [Point::new(0, 0)];
+        let goal = [Point::new(2, 1)];
+        for algorithm in ALGORITHMS {
+            let config = SearchConfig::new(algorithm);
+            assert_eq!(None, shortest_path(&walled, &start, &goal, config));
+        }
+    }
+}